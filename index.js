@@ -1,19 +1,4 @@
-import init, { vm_instantiate, vm_execute, vm_query } from "./pkg/cosmwebwasm.js";
-
-function normalize(state) {
-    state.codes = Object.fromEntries(state.codes);
-    state.contracts = Object.fromEntries(state.contracts);
-    state.storage = Object.fromEntries(state.storage);
-    state.storage =
-        Object.fromEntries(
-            Object.entries(state.storage).map(
-                ([k, v]) => [k, {
-                    data: Object.fromEntries(v.data),
-                    iterators: Object.fromEntries(v.iterators)
-                }]
-            )
-        );
-}
+import init, { Chain } from "./pkg/cosmwebwasm.js";
 
 function log(x) {
     console.log(JSON.stringify(x, null, 4));
@@ -22,72 +7,55 @@ function log(x) {
 async function run() {
     await init();
 
-    const codeId = 0x1337;
-    const sender = 0xC0DEC0DE;
-    const address = 0xCAFEBABE;
     const code = new Uint8Array(await fetch("./cw20_base.wasm").then(x => x.arrayBuffer()));
-    const state = {
-        storage: {},
-        codes: {
-            [codeId]: Array.from(code)
-        },
-        contracts: {
-            [address]: {
-                code_id: codeId,
-                admin: null,
-                label: ""
-            }
-        },
-        next_account_id: address + 1,
-        transaction_depth: 0,
-        gas: {
-            checkpoints: [ 10000000000000 ]
-        }
-    };
-
-    console.log(state);
+    const chain = new Chain();
+    const alice = chain.createAccount("alice", []);
+    const bob = chain.createAccount("bob", []);
+    chain.setDebugHandler(({ kind, address, depth, message }) => console.log(`[${kind}] ${address}@${depth}: ${message}`));
+    const codeId = chain.storeCode(code);
+    console.log(`Stored code ${codeId}`);
 
     console.log("-- Instantiate --");
-    const { state: state1, events: events1 } = vm_instantiate(sender, address, [], JSON.stringify(state), code, JSON.stringify({
+    const { address, events: events1 } = chain.instantiate(alice, codeId, "picasso", null, [], JSON.stringify({
         name: "Picasso",
         symbol: "PICA",
         decimals: 12,
         initial_balances: [],
         mint: {
-            minter: String(sender),
+            minter: alice,
             cap: null
         },
         marketing: null
     }));
-
-    normalize(state1);
+    console.log(`Instantiated ${address}`);
 
     events1.forEach(log);
-    console.log(state1);
 
     console.log("-- Mint --");
-    const { state: state2, events: events2 } = vm_execute(sender, address, [], JSON.stringify(state1), code, JSON.stringify({
+    const { events: events2 } = chain.execute(alice, address, [], JSON.stringify({
         mint: {
-            recipient: "10001",
+            recipient: bob,
             amount: "5555"
         }
     }));
 
     events2.forEach(log);
-    console.log(state2);
 
-    const tokenInfo = JSON.parse(atob(vm_query(sender, address, [], JSON.stringify(state1), code, {
+    const { json: tokenInfo } = chain.query(alice, address, {
         wasm: {
             smart: {
-                contract_addr: String(address),
+                contract_addr: address,
                 msg: btoa(JSON.stringify({
                     token_info: {}
                 }))
             }
         }
-    })));
+    });
     console.log("-- Token info --");
     log(tokenInfo);
+
+    console.log("-- State --");
+    console.log(JSON.parse(chain.exportState()));
 }
 
 export default run;
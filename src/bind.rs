@@ -1,54 +1,46 @@
 use crate::vm::*;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::rc::Rc;
 use alloc::string::String;
+use alloc::vec;
 use alloc::vec::Vec;
-use alloc::format;
 use cosmwasm_minimal_std::{
-    Binary, BlockInfo, Coin, ContractInfo, Empty, Env, Event, MessageInfo,
-    Timestamp,
+    Binary, Coin, ContractInfo, Empty, Env, Event, MessageInfo, QueryRequest, SystemResult,
+    TransactionInfo,
 };
-use cosmwasm_vm::system::cosmwasm_system_query;
+use cosmwasm_vm::system::{cosmwasm_system_query, CosmwasmCodeId, CosmwasmContractMeta};
 use cosmwasm_vm::{
-    executor::{ExecuteInput, InstantiateInput},
+    executor::{ExecuteInput, InstantiateInput, MigrateInput},
     system::cosmwasm_system_entrypoint,
 };
-use cosmwasm_vm_wasmi::{host_functions, new_wasmi_vm, WasmiImportResolver, WasmiVM};
+use cosmwasm_vm_wasmi::WasmiVM;
 use serde::{Deserialize, Serialize};
 use wasm_bindgen::prelude::*;
 
-pub fn vm_initialize<'a>(
-    extension: &'a mut SimpleWasmiVMExtension,
+pub fn vm_initialize(
+    extension: &mut SimpleWasmiVMExtension,
+    transaction: Option<TransactionInfo>,
     sender: BankAccount,
     address: BankAccount,
     funds: Vec<Coin>,
-    code: &[u8],
-) -> WasmiVM<SimpleWasmiVM<'a>> {
-    let host_functions_definitions = WasmiImportResolver(host_functions::definitions());
-    let module = new_wasmi_vm(&host_functions_definitions, code).unwrap();
-    WasmiVM(SimpleWasmiVM {
-        host_functions: host_functions_definitions
-            .0
-            .clone()
-            .into_iter()
-            .flat_map(|(_, modules)| modules.into_iter().map(|(_, function)| function))
-            .collect(),
-        executing_module: module,
-        env: Env {
-            block: BlockInfo {
-                height: 0xDEADC0DE,
-                time: Timestamp(0),
-                chain_id: "abstract-test".into(),
-            },
-            transaction: None,
+) -> Result<WasmiVM<SimpleWasmiVM>, SimpleVMError> {
+    let block = extension.block.info();
+    SimpleWasmiVM::new(
+        extension,
+        0,
+        Env {
+            block,
+            transaction,
             contract: ContractInfo {
                 address: address.into(),
             },
         },
-        info: MessageInfo {
+        MessageInfo {
             sender: sender.into(),
             funds,
         },
-        extension,
-    })
+    )
 }
 
 #[wasm_bindgen]
@@ -57,93 +49,797 @@ extern "C" {
     fn log(s: &str);
 }
 
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug, Default)]
+pub struct GasReport {
+    pub gas_used: u64,
+    pub gas_remaining: u64,
+    pub gas_breakdown: BTreeMap<String, u64>,
+}
+
+impl GasReport {
+    fn new(extension: &SimpleWasmiVMExtension) -> Self {
+        let gas_remaining = extension.gas.remaining();
+        GasReport {
+            gas_used: extension.gas_limit.saturating_sub(gas_remaining),
+            gas_remaining,
+            gas_breakdown: extension
+                .gas_breakdown
+                .iter()
+                .map(|(category, gas)| (String::from(*category), *gas))
+                .collect(),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct VMStep {
-    state: SimpleWasmiVMExtension,
+    /// Address of the contract created by an instantiation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    address: Option<BankAccount>,
     events: Vec<Event>,
     data: Option<Binary>,
+    #[serde(default)]
+    debug: Vec<DebugMessage>,
+    #[serde(flatten)]
+    gas: GasReport,
 }
 
-#[wasm_bindgen]
-pub fn vm_instantiate(
-    sender: BankAccount,
-    address: BankAccount,
-    funds: JsValue,
-    extension: JsValue,
-    code: &[u8],
-    message: JsValue,
-) -> Result<JsValue, String> {
-    let mut extension: SimpleWasmiVMExtension =
-        serde_json::from_str(&serde_wasm_bindgen::from_value::<String>(extension).map_err(|_| "failed to deserialize state")?)
-        .map_err(|_| "failed to deserialize state")?;
-    let funds = serde_wasm_bindgen::from_value(funds)
-        .map_err(|_| "failed to deserialize funds")?;
-    let mut vm = vm_initialize(&mut extension, sender, address, funds, code);
-    let message = serde_wasm_bindgen::from_value::<String>(message)
-        .map_err(|_| "failed to deserialize message")?;
-    let result = cosmwasm_system_entrypoint::<InstantiateInput<Empty>, WasmiVM<SimpleWasmiVM>>(
-        &mut vm,
-        message.as_bytes(),
-    );
+#[derive(Serialize, Deserialize)]
+pub struct VMFailure {
+    kind: SimpleVMErrorKind,
+    /// Contract that failed, the called one or the one of a failed sub call, if the
+    /// failure happened within a call.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    address: Option<BankAccount>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    entrypoint: Option<String>,
+    message: String,
+    #[serde(default)]
+    debug: Vec<DebugMessage>,
+    #[serde(flatten)]
+    gas: Option<GasReport>,
+}
+
+impl VMFailure {
+    fn deserialization(what: &str, error: impl core::fmt::Display) -> Self {
+        VMFailure {
+            kind: SimpleVMErrorKind::Deserialization,
+            address: None,
+            entrypoint: None,
+            message: format!("failed to deserialize {}: {}", what, error),
+            debug: Vec::new(),
+            gas: None,
+        }
+    }
+
+    fn serialization(what: &str, error: impl core::fmt::Display) -> Self {
+        VMFailure {
+            kind: SimpleVMErrorKind::Serialization,
+            address: None,
+            entrypoint: None,
+            message: format!("failed to serialize {}: {}", what, error),
+            debug: Vec::new(),
+            gas: None,
+        }
+    }
+}
+
+impl From<SimpleVMError> for VMFailure {
+    fn from(e: SimpleVMError) -> Self {
+        VMFailure {
+            kind: e.kind(),
+            address: e.sub_call_address().cloned(),
+            entrypoint: None,
+            message: format!("{}", e),
+            debug: Vec::new(),
+            gas: None,
+        }
+    }
+}
+
+impl From<VMFailure> for JsValue {
+    fn from(failure: VMFailure) -> Self {
+        to_js(&failure).unwrap_or_else(|e| e)
+    }
+}
+
+/// Run a top-level call with a refilled gas meter, reverting its state on failure.
+/// Like a transaction, the call takes the next index in the block whether it succeeds or not.
+fn vm_call(
+    extension: &mut SimpleWasmiVMExtension,
+    entrypoint: &str,
+    address: Option<BankAccount>,
+    f: impl FnOnce(&mut SimpleWasmiVMExtension) -> Result<(Option<Binary>, Vec<Event>), SimpleVMError>,
+) -> Result<VMStep, VMFailure> {
+    extension.gas_reset();
+    extension.debug_messages.clear();
+    let result = extension.atomically(f);
+    extension.transaction_index += 1;
+    let gas = GasReport::new(extension);
+    let debug = core::mem::take(&mut extension.debug_messages);
     match result {
-        Ok((data, events)) => Ok(serde_wasm_bindgen::to_value(&VMStep {
-            state: extension,
+        Ok((data, events)) => Ok(VMStep {
+            address: None,
             events,
             data,
-        }).map_err(|_| "failed to serialize state")?),
-        Err(e) => Err(format!("{}", e)),
+            debug,
+            gas,
+        }),
+        Err(e) => {
+            let failure = VMFailure::from(e);
+            Err(VMFailure {
+                address: failure.address.or(address),
+                entrypoint: Some(entrypoint.into()),
+                debug,
+                gas: Some(gas),
+                ..failure
+            })
+        }
+    }
+}
+
+/// Run the entrypoint of a top-level call, appending the admin changes of the contract
+/// that no event handler could receive to its events.
+fn with_admin_events(
+    vm: &mut WasmiVM<SimpleWasmiVM>,
+    f: impl FnOnce(&mut WasmiVM<SimpleWasmiVM>) -> Result<(Option<Binary>, Vec<Event>), SimpleVMError>,
+) -> Result<(Option<Binary>, Vec<Event>), SimpleVMError> {
+    let (data, mut events) = f(vm)?;
+    events.append(&mut vm.0.admin_events);
+    Ok((data, events))
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn vm_instantiate(
+    extension: &mut SimpleWasmiVMExtension,
+    sender: BankAccount,
+    code_id: CosmwasmCodeId,
+    admin: Option<BankAccount>,
+    label: String,
+    funds: Vec<Coin>,
+    message: &[u8],
+    salt: Option<&[u8]>,
+) -> Result<VMStep, VMFailure> {
+    let mut contract_address = None;
+    let result = vm_call(extension, "instantiate", None, |extension| {
+        extension.address_mode.validate(&sender)?;
+        if let Some(admin) = &admin {
+            extension.address_mode.validate(admin)?;
+        }
+        let address = extension.create_contract(
+            sender.clone(),
+            CosmwasmContractMeta {
+                code_id,
+                admin,
+                label,
+            },
+            salt,
+        )?;
+        contract_address = Some(address.clone());
+        extension.transfer(sender.clone(), address.clone(), &funds)?;
+        let transaction = extension.transaction_info();
+        let mut vm = vm_initialize(extension, Some(transaction), sender, address, funds)?;
+        with_admin_events(&mut vm, |vm| {
+            cosmwasm_system_entrypoint::<InstantiateInput<Empty>, WasmiVM<SimpleWasmiVM>>(
+                vm, message,
+            )
+        })
+    });
+    match result {
+        Ok(step) => Ok(VMStep {
+            address: contract_address,
+            ..step
+        }),
+        Err(failure) => Err(VMFailure {
+            address: failure.address.or(contract_address),
+            ..failure
+        }),
     }
 }
 
-#[wasm_bindgen]
 pub fn vm_execute(
+    extension: &mut SimpleWasmiVMExtension,
+    sender: BankAccount,
+    address: BankAccount,
+    funds: Vec<Coin>,
+    message: &[u8],
+) -> Result<VMStep, VMFailure> {
+    vm_call(extension, "execute", Some(address.clone()), |extension| {
+        extension.address_mode.validate(&sender)?;
+        extension.address_mode.validate(&address)?;
+        extension.transfer(sender.clone(), address.clone(), &funds)?;
+        let transaction = extension.transaction_info();
+        let mut vm = vm_initialize(extension, Some(transaction), sender, address, funds)?;
+        with_admin_events(&mut vm, |vm| {
+            cosmwasm_system_entrypoint::<ExecuteInput<Empty>, WasmiVM<SimpleWasmiVM>>(vm, message)
+        })
+    })
+}
+
+pub fn vm_migrate(
+    extension: &mut SimpleWasmiVMExtension,
+    sender: BankAccount,
+    address: BankAccount,
+    new_code_id: CosmwasmCodeId,
+    message: &[u8],
+) -> Result<VMStep, VMFailure> {
+    vm_call(extension, "migrate", Some(address.clone()), |extension| {
+        extension.address_mode.validate(&sender)?;
+        extension.address_mode.validate(&address)?;
+        let CosmwasmContractMeta { admin, label, .. } = extension
+            .contracts
+            .get(&address)
+            .cloned()
+            .ok_or_else(|| SimpleVMError::ContractNotFound(address.clone()))?;
+        extension.update_contract_meta(
+            sender.clone(),
+            address.clone(),
+            CosmwasmContractMeta {
+                code_id: new_code_id,
+                admin,
+                label,
+            },
+        )?;
+        let transaction = extension.transaction_info();
+        let mut vm = vm_initialize(extension, Some(transaction), sender, address, vec![])?;
+        with_admin_events(&mut vm, |vm| {
+            cosmwasm_system_entrypoint::<MigrateInput<Empty>, WasmiVM<SimpleWasmiVM>>(vm, message)
+        })
+    })
+}
+
+/// Change the admin of a contract, emitting the event wasmd emits for both
+/// `UpdateAdmin` and `ClearAdmin`.
+pub fn vm_update_admin(
+    extension: &mut SimpleWasmiVMExtension,
+    sender: BankAccount,
+    address: BankAccount,
+    admin: Option<BankAccount>,
+) -> Result<VMStep, VMFailure> {
+    let entrypoint = if admin.is_some() {
+        "update_admin"
+    } else {
+        "clear_admin"
+    };
+    vm_call(extension, entrypoint, Some(address.clone()), |extension| {
+        extension.address_mode.validate(&sender)?;
+        extension.address_mode.validate(&address)?;
+        if let Some(admin) = &admin {
+            extension.address_mode.validate(admin)?;
+        }
+        let event = update_admin_event(&address, admin.as_ref());
+        extension.update_contract_admin(sender, address.clone(), admin)?;
+        Ok((None, vec![event]))
+    })
+}
+
+pub fn vm_sudo(
+    extension: &mut SimpleWasmiVMExtension,
+    address: BankAccount,
+    message: &[u8],
+) -> Result<VMStep, VMFailure> {
+    vm_call(extension, "sudo", Some(address.clone()), |extension| {
+        extension.address_mode.validate(&address)?;
+        // Sudo is not given any `MessageInfo`, the contract stands as its own sender.
+        let transaction = extension.transaction_info();
+        let mut vm = vm_initialize(
+            extension,
+            Some(transaction),
+            address.clone(),
+            address,
+            vec![],
+        )?;
+        with_admin_events(&mut vm, |vm| {
+            cosmwasm_system_entrypoint::<SudoInput<Empty>, WasmiVM<SimpleWasmiVM>>(vm, message)
+        })
+    })
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct VMQuery {
+    data: Binary,
+    /// The `data` parsed as JSON, if it is valid JSON.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    json: Option<serde_json::Value>,
+    #[serde(default)]
+    debug: Vec<DebugMessage>,
+    #[serde(flatten)]
+    gas: GasReport,
+}
+
+/// Run a query with a refilled gas meter, any failure of the VM, the chain or the
+/// contract is reported as a `VMFailure`.
+pub fn vm_query(
+    extension: &mut SimpleWasmiVMExtension,
     sender: BankAccount,
     address: BankAccount,
-    funds: JsValue,
-    extension: JsValue,
-    code: &[u8],
-    message: JsValue,
-) -> Result<JsValue, String> {
-    let mut extension: SimpleWasmiVMExtension =
-        serde_json::from_str(&serde_wasm_bindgen::from_value::<String>(extension).map_err(|_| "failed to deserialize state")?)
-        .map_err(|_| "failed to deserialize state")?;
-    let funds = serde_wasm_bindgen::from_value(funds)
-        .map_err(|_| "failed to deserialize funds")?;
-    let mut vm = vm_initialize(&mut extension, sender, address, funds, code);
-    let message = serde_wasm_bindgen::from_value::<String>(message)
-        .map_err(|_| "failed to deserialize message")?;
-    let result = cosmwasm_system_entrypoint::<ExecuteInput<Empty>, WasmiVM<SimpleWasmiVM>>(
-        &mut vm,
-        message.as_bytes(),
-    );
+    query: QueryRequest<Empty>,
+) -> Result<VMQuery, VMFailure> {
+    extension.gas_reset();
+    extension.debug_messages.clear();
+    let result = extension
+        .address_mode
+        .validate(&sender)
+        .and_then(|()| extension.address_mode.validate(&address))
+        .and_then(|()| vm_initialize(extension, None, sender, address.clone(), vec![]))
+        .and_then(|mut vm| cosmwasm_system_query(&mut vm, query));
+    let gas = GasReport::new(extension);
+    let debug = core::mem::take(&mut extension.debug_messages);
+    let result = match result {
+        Ok(SystemResult::Ok(result)) => result.into_result().map_err(|message| {
+            (
+                SimpleVMErrorKind::ContractExecutionFailure,
+                format!("contract execution failed: {}", message),
+            )
+        }),
+        Ok(SystemResult::Err(e)) => Err((SimpleVMErrorKind::System, format!("{:?}", e))),
+        Err(e) => Err((e.kind(), format!("{}", e))),
+    };
     match result {
-        Ok((data, events)) => Ok(serde_wasm_bindgen::to_value(&VMStep {
-            state: extension,
-            events,
+        Ok(data) => Ok(VMQuery {
+            json: serde_json::from_slice(data.as_slice()).ok(),
             data,
-        }).map_err(|_| "failed to serialize state")?),
-        Err(e) => Err(format!("{}", e)),
+            debug,
+            gas,
+        }),
+        Err((kind, message)) => Err(VMFailure {
+            kind,
+            address: Some(address),
+            entrypoint: Some("query".into()),
+            message,
+            debug,
+            gas: Some(gas),
+        }),
     }
 }
 
+fn to_js<T: Serialize>(value: &T) -> Result<JsValue, JsValue> {
+    value
+        .serialize(&serde_wasm_bindgen::Serializer::json_compatible())
+        .map_err(|_| "failed to serialize result".into())
+}
+
+/// A chain living in wasm memory, keeping its state in between calls.
 #[wasm_bindgen]
-pub fn vm_query(
-    sender: BankAccount,
-    address: BankAccount,
-    funds: JsValue,
-    extension: JsValue,
-    code: &[u8],
-    message: JsValue,
-) -> Result<JsValue, String> {
-    let mut extension: SimpleWasmiVMExtension = serde_json::from_str(
-        &serde_wasm_bindgen::from_value::<String>(extension)
-            .map_err(|_| "failed to deserialize vm state")?,
-    )
-    .map_err(|_| "failed to deserialize vm state")?;
-    let funds = serde_wasm_bindgen::from_value(funds).map_err(|_| "failed to deserialize funds")?;
-    let mut vm = vm_initialize(&mut extension, sender, address, funds, code);
-    let query =
-        serde_wasm_bindgen::from_value(message).map_err(|_| "failed to deserialize query")?;
-    let result = cosmwasm_system_query(&mut vm, query);
-    Ok(serde_wasm_bindgen::to_value(&result.unwrap().unwrap().into_result().unwrap()).unwrap())
+pub struct Chain {
+    extension: SimpleWasmiVMExtension,
+}
+
+impl Default for Chain {
+    fn default() -> Self {
+        Chain {
+            extension: SimpleWasmiVMExtension::new(DEFAULT_GAS_LIMIT, AddressMode::Numeric),
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl Chain {
+    /// Create a chain with numeric accounts, or bech32 accounts if a `bech32_prefix` is given.
+    #[wasm_bindgen(constructor)]
+    pub fn new(bech32_prefix: Option<String>) -> Result<Chain, JsValue> {
+        let address_mode = match bech32_prefix {
+            Some(prefix) => AddressMode::bech32(prefix).map_err(VMFailure::from)?,
+            None => AddressMode::Numeric,
+        };
+        Ok(Chain {
+            extension: SimpleWasmiVMExtension::new(DEFAULT_GAS_LIMIT, address_mode),
+        })
+    }
+
+    /// Replace the chain state, the debug handler is not part of it and is kept.
+    #[wasm_bindgen(js_name = importState)]
+    pub fn import_state(&mut self, state: &str) -> Result<(), JsValue> {
+        let extension: SimpleWasmiVMExtension =
+            serde_json::from_str(state).map_err(|e| VMFailure::deserialization("state", e))?;
+        let debug_handler = core::mem::take(&mut self.extension.debug_handler);
+        self.extension = SimpleWasmiVMExtension {
+            debug_handler,
+            ..extension
+        };
+        Ok(())
+    }
+
+    #[wasm_bindgen(js_name = exportState)]
+    pub fn export_state(&self) -> Result<String, JsValue> {
+        Ok(serde_json::to_string(&self.extension)
+            .map_err(|e| VMFailure::serialization("state", e))?)
+    }
+
+    #[wasm_bindgen(js_name = setGasLimit)]
+    pub fn set_gas_limit(&mut self, gas_limit: u64) {
+        self.extension.gas_limit = gas_limit;
+    }
+
+    #[wasm_bindgen(js_name = setGasRules)]
+    pub fn set_gas_rules(&mut self, rules: JsValue) -> Result<(), JsValue> {
+        let rules = serde_wasm_bindgen::from_value(rules)
+            .map_err(|e| VMFailure::deserialization("gas rules", e))?;
+        self.extension.set_gas_rules(rules);
+        Ok(())
+    }
+
+    /// Call `handler` with every debug message, as soon as a contract emits it.
+    /// The chain is borrowed while the contract runs, `handler` must not call back into it.
+    #[wasm_bindgen(js_name = setDebugHandler)]
+    pub fn set_debug_handler(&mut self, handler: Option<js_sys::Function>) {
+        self.extension.debug_handler = DebugHandler(handler.map(|handler| {
+            Rc::new(move |message: &DebugMessage| {
+                if let Ok(message) = to_js(message) {
+                    let _ = handler.call1(&JsValue::NULL, &message);
+                }
+            }) as Rc<dyn Fn(&DebugMessage)>
+        }));
+    }
+
+    #[wasm_bindgen(js_name = setBlockHeight)]
+    pub fn set_block_height(&mut self, height: u64) {
+        self.extension.set_block_height(height);
+    }
+
+    /// Set the block time, in nanoseconds since the unix epoch.
+    #[wasm_bindgen(js_name = setBlockTime)]
+    pub fn set_block_time(&mut self, time: u64) {
+        self.extension.set_block_time(time);
+    }
+
+    #[wasm_bindgen(js_name = setChainId)]
+    pub fn set_chain_id(&mut self, chain_id: String) {
+        self.extension.block.chain_id = chain_id;
+    }
+
+    #[wasm_bindgen(js_name = advanceBlocks)]
+    pub fn advance_blocks(&mut self, blocks: u64, seconds_per_block: u64) {
+        self.extension.advance_blocks(blocks, seconds_per_block);
+    }
+
+    /// Address of the account named `name`, created if it does not exist, minting `funds` to it.
+    #[wasm_bindgen(js_name = createAccount)]
+    pub fn create_account(&mut self, name: &str, funds: JsValue) -> Result<String, JsValue> {
+        let funds: Vec<Coin> = serde_wasm_bindgen::from_value(funds)
+            .map_err(|e| VMFailure::deserialization("funds", e))?;
+        let BankAccount(account) = self
+            .extension
+            .named_account(name, &funds)
+            .map_err(VMFailure::from)?;
+        Ok(account)
+    }
+
+    pub fn account(&self, name: &str) -> Option<String> {
+        self.extension
+            .accounts
+            .get(name)
+            .map(|BankAccount(account)| account.clone())
+    }
+
+    pub fn mint(&mut self, account: BankAccount, funds: JsValue) -> Result<(), JsValue> {
+        let funds: Vec<Coin> = serde_wasm_bindgen::from_value(funds)
+            .map_err(|e| VMFailure::deserialization("funds", e))?;
+        Ok(self
+            .extension
+            .address_mode
+            .validate(&account)
+            .and_then(|()| self.extension.mint(account, &funds))
+            .map_err(VMFailure::from)?)
+    }
+
+    pub fn balances(&self, account: BankAccount) -> Result<JsValue, JsValue> {
+        self.extension
+            .address_mode
+            .validate(&account)
+            .map_err(VMFailure::from)?;
+        to_js(&self.extension.all_balance(&account))
+    }
+
+    #[wasm_bindgen(js_name = storeCode)]
+    pub fn store_code(&mut self, code: Vec<u8>) -> Result<CosmwasmCodeId, JsValue> {
+        Ok(self.extension.store_code(code).map_err(VMFailure::from)?)
+    }
+
+    pub fn instantiate(
+        &mut self,
+        sender: BankAccount,
+        code_id: CosmwasmCodeId,
+        label: String,
+        admin: Option<String>,
+        funds: JsValue,
+        message: &str,
+    ) -> Result<JsValue, JsValue> {
+        let funds = serde_wasm_bindgen::from_value(funds)
+            .map_err(|e| VMFailure::deserialization("funds", e))?;
+        match vm_instantiate(
+            &mut self.extension,
+            sender,
+            code_id,
+            admin.map(BankAccount),
+            label,
+            funds,
+            message.as_bytes(),
+            None,
+        ) {
+            Ok(step) => to_js(&step),
+            Err(failure) => Err(failure.into()),
+        }
+    }
+
+    /// Instantiate a contract at an address predictable from the checksum of the code,
+    /// the sender and `salt`. Only available with bech32 accounts.
+    #[allow(clippy::too_many_arguments)]
+    pub fn instantiate2(
+        &mut self,
+        sender: BankAccount,
+        code_id: CosmwasmCodeId,
+        label: String,
+        admin: Option<String>,
+        funds: JsValue,
+        message: &str,
+        salt: Vec<u8>,
+    ) -> Result<JsValue, JsValue> {
+        let funds = serde_wasm_bindgen::from_value(funds)
+            .map_err(|e| VMFailure::deserialization("funds", e))?;
+        match vm_instantiate(
+            &mut self.extension,
+            sender,
+            code_id,
+            admin.map(BankAccount),
+            label,
+            funds,
+            message.as_bytes(),
+            Some(&salt),
+        ) {
+            Ok(step) => to_js(&step),
+            Err(failure) => Err(failure.into()),
+        }
+    }
+
+    pub fn execute(
+        &mut self,
+        sender: BankAccount,
+        address: BankAccount,
+        funds: JsValue,
+        message: &str,
+    ) -> Result<JsValue, JsValue> {
+        let funds = serde_wasm_bindgen::from_value(funds)
+            .map_err(|e| VMFailure::deserialization("funds", e))?;
+        match vm_execute(
+            &mut self.extension,
+            sender,
+            address,
+            funds,
+            message.as_bytes(),
+        ) {
+            Ok(step) => to_js(&step),
+            Err(failure) => Err(failure.into()),
+        }
+    }
+
+    pub fn migrate(
+        &mut self,
+        sender: BankAccount,
+        address: BankAccount,
+        new_code_id: CosmwasmCodeId,
+        message: &str,
+    ) -> Result<JsValue, JsValue> {
+        match vm_migrate(
+            &mut self.extension,
+            sender,
+            address,
+            new_code_id,
+            message.as_bytes(),
+        ) {
+            Ok(step) => to_js(&step),
+            Err(failure) => Err(failure.into()),
+        }
+    }
+
+    #[wasm_bindgen(js_name = updateAdmin)]
+    pub fn update_admin(
+        &mut self,
+        sender: BankAccount,
+        address: BankAccount,
+        new_admin: BankAccount,
+    ) -> Result<JsValue, JsValue> {
+        match vm_update_admin(&mut self.extension, sender, address, Some(new_admin)) {
+            Ok(step) => to_js(&step),
+            Err(failure) => Err(failure.into()),
+        }
+    }
+
+    #[wasm_bindgen(js_name = clearAdmin)]
+    pub fn clear_admin(
+        &mut self,
+        sender: BankAccount,
+        address: BankAccount,
+    ) -> Result<JsValue, JsValue> {
+        match vm_update_admin(&mut self.extension, sender, address, None) {
+            Ok(step) => to_js(&step),
+            Err(failure) => Err(failure.into()),
+        }
+    }
+
+    pub fn sudo(&mut self, address: BankAccount, message: &str) -> Result<JsValue, JsValue> {
+        match vm_sudo(&mut self.extension, address, message.as_bytes()) {
+            Ok(step) => to_js(&step),
+            Err(failure) => Err(failure.into()),
+        }
+    }
+
+    pub fn query(
+        &mut self,
+        sender: BankAccount,
+        address: BankAccount,
+        query: JsValue,
+    ) -> Result<JsValue, JsValue> {
+        let query = serde_wasm_bindgen::from_value(query)
+            .map_err(|e| VMFailure::deserialization("query", e))?;
+        match vm_query(&mut self.extension, sender, address, query) {
+            Ok(result) => to_js(&result),
+            Err(failure) => Err(failure.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+    use cosmwasm_minimal_std::Addr;
+
+    #[test]
+    fn failures_carry_their_kind_and_call() {
+        let mut extension = SimpleWasmiVMExtension::new(DEFAULT_GAS_LIMIT, AddressMode::Numeric);
+
+        let failure = vm_execute(
+            &mut extension,
+            BankAccount("001".into()),
+            BankAccount("002".into()),
+            vec![Coin {
+                denom: "uatom".into(),
+                amount: 1u128.into(),
+            }],
+            b"{}",
+        )
+        .err()
+        .unwrap();
+
+        assert_eq!(failure.kind, SimpleVMErrorKind::InsufficientFunds);
+        assert_eq!(failure.address, Some(BankAccount("002".into())));
+        assert_eq!(failure.entrypoint.as_deref(), Some("execute"));
+        assert_eq!(failure.message, "insufficient funds");
+        assert!(failure.gas.is_some());
+        assert_eq!(extension.transaction_index, 1);
+    }
+
+    #[test]
+    fn instantiating_an_unknown_code_fails() {
+        let mut extension = SimpleWasmiVMExtension::new(DEFAULT_GAS_LIMIT, AddressMode::Numeric);
+
+        let failure = vm_instantiate(
+            &mut extension,
+            BankAccount("001".into()),
+            1,
+            None,
+            String::new(),
+            vec![],
+            b"{}",
+            None,
+        )
+        .err()
+        .unwrap();
+
+        assert_eq!(failure.kind, SimpleVMErrorKind::CodeNotFound);
+        assert_eq!(failure.address, None);
+        assert_eq!(failure.entrypoint.as_deref(), Some("instantiate"));
+    }
+
+    const CW20_BASE: &[u8] = include_bytes!("../cw20_base.wasm");
+
+    fn token_info(address: &str) -> QueryRequest<Empty> {
+        serde_json::from_str(&format!(
+            r#"{{"wasm":{{"smart":{{"contract_addr":"{}","msg":"eyJ0b2tlbl9pbmZvIjp7fX0="}}}}}}"#,
+            address
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn queries_return_the_parsed_json() {
+        let mut extension = SimpleWasmiVMExtension::new(DEFAULT_GAS_LIMIT, AddressMode::Numeric);
+        let code_id = extension.store_code(CW20_BASE.to_vec()).unwrap();
+        let step = vm_instantiate(
+            &mut extension,
+            BankAccount("1000".into()),
+            code_id,
+            None,
+            "token".into(),
+            vec![],
+            br#"{"name":"Picasso","symbol":"PICA","decimals":12,"initial_balances":[]}"#,
+            None,
+        )
+        .ok()
+        .unwrap();
+        let address = step.address.unwrap();
+
+        let result = vm_query(
+            &mut extension,
+            BankAccount("1000".into()),
+            address.clone(),
+            token_info(&Addr::from(address).to_string()),
+        )
+        .ok()
+        .unwrap();
+
+        assert_eq!(result.json.unwrap()["symbol"], "PICA");
+    }
+
+    #[test]
+    fn queries_to_unknown_contracts_fail() {
+        let mut extension = SimpleWasmiVMExtension::new(DEFAULT_GAS_LIMIT, AddressMode::Numeric);
+
+        let failure = vm_query(
+            &mut extension,
+            BankAccount("001".into()),
+            BankAccount("002".into()),
+            token_info("002"),
+        )
+        .err()
+        .unwrap();
+
+        assert_eq!(failure.kind, SimpleVMErrorKind::ContractNotFound);
+        assert_eq!(failure.entrypoint.as_deref(), Some("query"));
+    }
+
+    #[test]
+    fn only_the_admin_updates_or_clears_the_admin() {
+        let mut extension = SimpleWasmiVMExtension::new(DEFAULT_GAS_LIMIT, AddressMode::Numeric);
+        extension.set_code(1, Some(SimpleWasmiVMCode::new(vec![0])));
+        extension.set_contract(
+            BankAccount("001".into()),
+            Some(CosmwasmContractMeta {
+                code_id: 1,
+                admin: Some(BankAccount("002".into())),
+                label: "token".into(),
+            }),
+        );
+
+        let failure = vm_update_admin(
+            &mut extension,
+            BankAccount("003".into()),
+            BankAccount("001".into()),
+            None,
+        )
+        .err()
+        .unwrap();
+        assert_eq!(failure.kind, SimpleVMErrorKind::Unauthorized);
+        assert_eq!(failure.entrypoint.as_deref(), Some("clear_admin"));
+
+        let step = vm_update_admin(
+            &mut extension,
+            BankAccount("002".into()),
+            BankAccount("001".into()),
+            Some(BankAccount("003".into())),
+        )
+        .ok()
+        .unwrap();
+        assert_eq!(
+            step.events,
+            vec![Event::new("update_contract_admin")
+                .add_attribute("_contract_address", "001")
+                .add_attribute("new_admin_address", "003")]
+        );
+
+        let step = vm_update_admin(
+            &mut extension,
+            BankAccount("003".into()),
+            BankAccount("001".into()),
+            None,
+        )
+        .ok()
+        .unwrap();
+        assert_eq!(
+            step.events,
+            vec![update_admin_event(&BankAccount("001".into()), None)]
+        );
+        assert_eq!(extension.contracts[&BankAccount("001".into())].admin, None);
+    }
 }
@@ -0,0 +1,517 @@
+use alloc::vec::Vec;
+use core::fmt::Display;
+use ed25519_zebra::{Signature as Ed25519Signature, VerificationKey as Ed25519VerificationKey};
+use k256::ecdsa::{
+    signature::hazmat::PrehashVerifier, RecoveryId, Signature as Secp256k1Signature,
+    VerifyingKey as Secp256k1VerifyingKey,
+};
+
+pub const SECP256K1_VERIFY_COST: u64 = 154_000;
+pub const SECP256K1_RECOVER_PUBKEY_COST: u64 = 162_000;
+pub const ED25519_VERIFY_COST: u64 = 63_000;
+pub const ED25519_BATCH_VERIFY_COST: u64 = 31_500;
+pub const ED25519_BATCH_VERIFY_ONE_PUBKEY_COST: u64 = 15_750;
+
+const MESSAGE_HASH_LENGTH: usize = 32;
+const SECP256K1_SIGNATURE_LENGTH: usize = 64;
+const SECP256K1_COMPRESSED_PUBKEY_LENGTH: usize = 33;
+const SECP256K1_UNCOMPRESSED_PUBKEY_LENGTH: usize = 65;
+const ED25519_SIGNATURE_LENGTH: usize = 64;
+const ED25519_PUBKEY_LENGTH: usize = 32;
+
+/// Errors of the crypto functions, named after the cosmwasm-crypto ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CryptoError {
+    InvalidHashFormat,
+    InvalidSignatureFormat,
+    InvalidPubkeyFormat,
+    InvalidRecoveryParam,
+    /// A batch whose numbers of messages, signatures and public keys do not match.
+    BatchErr,
+    /// Well formed inputs the underlying implementation rejects, e.g. a point off the curve.
+    GenericErr,
+}
+
+impl Display for CryptoError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            CryptoError::InvalidHashFormat => write!(f, "invalid hash format"),
+            CryptoError::InvalidSignatureFormat => write!(f, "invalid signature format"),
+            CryptoError::InvalidPubkeyFormat => write!(f, "invalid public key format"),
+            CryptoError::InvalidRecoveryParam => {
+                write!(
+                    f,
+                    "invalid recovery parameter, supported values are 0 and 1"
+                )
+            }
+            CryptoError::BatchErr => write!(
+                f,
+                "mismatched number of messages, signatures and public keys in batch"
+            ),
+            CryptoError::GenericErr => write!(f, "crypto error"),
+        }
+    }
+}
+
+fn secp256k1_message_hash(message_hash: &[u8]) -> Result<&[u8], CryptoError> {
+    if message_hash.len() != MESSAGE_HASH_LENGTH {
+        return Err(CryptoError::InvalidHashFormat);
+    }
+    Ok(message_hash)
+}
+
+fn secp256k1_signature(signature: &[u8]) -> Result<Secp256k1Signature, CryptoError> {
+    if signature.len() != SECP256K1_SIGNATURE_LENGTH {
+        return Err(CryptoError::InvalidSignatureFormat);
+    }
+    Secp256k1Signature::from_slice(signature).map_err(|_| CryptoError::GenericErr)
+}
+
+fn secp256k1_public_key(public_key: &[u8]) -> Result<Secp256k1VerifyingKey, CryptoError> {
+    let well_formed = match public_key.len() {
+        SECP256K1_COMPRESSED_PUBKEY_LENGTH => matches!(public_key[0], 0x02 | 0x03),
+        SECP256K1_UNCOMPRESSED_PUBKEY_LENGTH => public_key[0] == 0x04,
+        _ => false,
+    };
+    if !well_formed {
+        return Err(CryptoError::InvalidPubkeyFormat);
+    }
+    Secp256k1VerifyingKey::from_sec1_bytes(public_key).map_err(|_| CryptoError::GenericErr)
+}
+
+/// Verify a 64 bytes `r || s` signature of a 32 bytes prehashed message against
+/// a compressed or uncompressed SEC1 public key.
+/// Only well formed signatures that do not verify are reported as `false`.
+pub fn secp256k1_verify(
+    message_hash: &[u8],
+    signature: &[u8],
+    public_key: &[u8],
+) -> Result<bool, CryptoError> {
+    let message_hash = secp256k1_message_hash(message_hash)?;
+    let signature = secp256k1_signature(signature)?;
+    // Like wasmd, high S signatures are accepted by normalizing them.
+    let signature = signature.normalize_s().unwrap_or(signature);
+    let public_key = secp256k1_public_key(public_key)?;
+    Ok(public_key.verify_prehash(message_hash, &signature).is_ok())
+}
+
+/// Recover the 65 bytes uncompressed public key that produced `signature` over
+/// the 32 bytes prehashed message. Only the recovery params `0` and `1` are valid.
+pub fn secp256k1_recover_pubkey(
+    message_hash: &[u8],
+    signature: &[u8],
+    recovery_param: u8,
+) -> Result<Vec<u8>, CryptoError> {
+    let message_hash = secp256k1_message_hash(message_hash)?;
+    let signature = secp256k1_signature(signature)?;
+    if recovery_param > 1 {
+        return Err(CryptoError::InvalidRecoveryParam);
+    }
+    let recovery_id =
+        RecoveryId::from_byte(recovery_param).ok_or(CryptoError::InvalidRecoveryParam)?;
+    // A normalized S belongs to the negated nonce point, whose y coordinate has the opposite parity.
+    let (signature, recovery_id) = match signature.normalize_s() {
+        Some(normalized) => (
+            normalized,
+            RecoveryId::new(!recovery_id.is_y_odd(), recovery_id.is_x_reduced()),
+        ),
+        None => (signature, recovery_id),
+    };
+    let public_key =
+        Secp256k1VerifyingKey::recover_from_prehash(message_hash, &signature, recovery_id)
+            .map_err(|_| CryptoError::GenericErr)?;
+    Ok(public_key.to_encoded_point(false).as_bytes().to_vec())
+}
+
+/// Verify a 64 bytes ed25519 signature of `message` against a 32 bytes public key.
+/// Like cosmwasm-crypto, a public key that is not a curve point does not verify.
+pub fn ed25519_verify(
+    message: &[u8],
+    signature: &[u8],
+    public_key: &[u8],
+) -> Result<bool, CryptoError> {
+    if signature.len() != ED25519_SIGNATURE_LENGTH {
+        return Err(CryptoError::InvalidSignatureFormat);
+    }
+    if public_key.len() != ED25519_PUBKEY_LENGTH {
+        return Err(CryptoError::InvalidPubkeyFormat);
+    }
+    let signature =
+        Ed25519Signature::from_slice(signature).map_err(|_| CryptoError::InvalidSignatureFormat)?;
+    Ok(Ed25519VerificationKey::try_from(public_key)
+        .and_then(|public_key| public_key.verify(&signature, message))
+        .is_ok())
+}
+
+/// Gas cost of an ed25519 batch, cheaper per signature when a single key signs them all.
+pub fn ed25519_batch_verify_cost(signatures: &[&[u8]], public_keys: &[&[u8]]) -> u64 {
+    let cost = if public_keys.len() == 1 {
+        ED25519_BATCH_VERIFY_ONE_PUBKEY_COST
+    } else {
+        ED25519_BATCH_VERIFY_COST
+    };
+    cost.saturating_mul(signatures.len() as u64)
+}
+
+/// Verify a batch of ed25519 signatures, following the CosmWasm batch shapes:
+/// - as many messages as signatures and public keys, verified pairwise;
+/// - one message signed by many public keys;
+/// - many messages signed by one public key.
+///
+/// Any other shape is a batch error. An empty batch is valid.
+pub fn ed25519_batch_verify(
+    messages: &[&[u8]],
+    signatures: &[&[u8]],
+    public_keys: &[&[u8]],
+) -> Result<bool, CryptoError> {
+    let (messages_len, signatures_len, public_keys_len) =
+        (messages.len(), signatures.len(), public_keys.len());
+    let batch: Vec<(&[u8], &[u8], &[u8])> =
+        if messages_len == signatures_len && messages_len == public_keys_len {
+            (0..signatures_len)
+                .map(|i| (messages[i], signatures[i], public_keys[i]))
+                .collect()
+        } else if messages_len == 1 && signatures_len == public_keys_len {
+            (0..signatures_len)
+                .map(|i| (messages[0], signatures[i], public_keys[i]))
+                .collect()
+        } else if public_keys_len == 1 && messages_len == signatures_len {
+            (0..signatures_len)
+                .map(|i| (messages[i], signatures[i], public_keys[0]))
+                .collect()
+        } else {
+            return Err(CryptoError::BatchErr);
+        };
+    // Every signature is checked so that a malformed one is reported even after a failure.
+    batch
+        .into_iter()
+        .try_fold(true, |verified, (message, signature, public_key)| {
+            Ok(ed25519_verify(message, signature, public_key)? && verified)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::String;
+    use ed25519_zebra::SigningKey as Ed25519SigningKey;
+    use k256::ecdsa::SigningKey;
+    use serde::Deserialize;
+    use sha2::{Digest, Sha256};
+
+    // Test data from cosmwasm-crypto, originally from cosmjs.
+    const SECP256K1_TESTS_JSON: &str = include_str!("../testdata/secp256k1_tests.json");
+    // Test data from cosmwasm-crypto, originally from RFC 8032 and cosmjs.
+    const ED25519_TESTS_JSON: &str = include_str!("../testdata/ed25519_tests.json");
+
+    #[derive(Deserialize)]
+    struct Secp256k1Test {
+        message: String,
+        message_hash: String,
+        signature: String,
+        pubkey: String,
+    }
+
+    #[derive(Deserialize)]
+    struct Ed25519Test {
+        privkey: String,
+        pubkey: String,
+        message: String,
+        signature: String,
+    }
+
+    fn secp256k1_tests() -> Vec<Secp256k1Test> {
+        serde_json::from_str(SECP256K1_TESTS_JSON).unwrap()
+    }
+
+    type Ed25519Batch = (Vec<Vec<u8>>, Vec<Vec<u8>>, Vec<Vec<u8>>);
+
+    /// Messages, signatures and public keys of the ed25519 test data.
+    fn ed25519_tests() -> Ed25519Batch {
+        let tests: Vec<Ed25519Test> = serde_json::from_str(ED25519_TESTS_JSON).unwrap();
+        let mut messages = Vec::new();
+        let mut signatures = Vec::new();
+        let mut public_keys = Vec::new();
+        for test in tests {
+            let private_key = hex::decode(&test.privkey).unwrap();
+            let public_key = hex::decode(&test.pubkey).unwrap();
+            let signing_key = Ed25519SigningKey::try_from(private_key.as_slice()).unwrap();
+            assert_eq!(
+                <[u8; 32]>::from(Ed25519VerificationKey::from(&signing_key)).as_slice(),
+                public_key
+            );
+            messages.push(hex::decode(&test.message).unwrap());
+            signatures.push(hex::decode(&test.signature).unwrap());
+            public_keys.push(public_key);
+        }
+        (messages, signatures, public_keys)
+    }
+
+    fn slices(items: &[Vec<u8>]) -> Vec<&[u8]> {
+        items.iter().map(Vec::as_slice).collect()
+    }
+
+    #[test]
+    fn secp256k1_verify_works() {
+        for (i, test) in secp256k1_tests().into_iter().enumerate() {
+            let message_hash = hex::decode(&test.message_hash).unwrap();
+            assert_eq!(
+                Sha256::digest(hex::decode(&test.message).unwrap()).to_vec(),
+                message_hash
+            );
+            let signature = hex::decode(&test.signature).unwrap();
+            let public_key = hex::decode(&test.pubkey).unwrap();
+            let compressed = Secp256k1VerifyingKey::from_sec1_bytes(&public_key)
+                .unwrap()
+                .to_encoded_point(true)
+                .as_bytes()
+                .to_vec();
+
+            assert_eq!(
+                secp256k1_verify(&message_hash, &signature, &public_key),
+                Ok(true),
+                "test case {}",
+                i
+            );
+            assert_eq!(
+                secp256k1_verify(&message_hash, &signature, &compressed),
+                Ok(true),
+                "test case {}",
+                i
+            );
+            let mut other_hash = message_hash.clone();
+            other_hash[0] ^= 1;
+            assert_eq!(
+                secp256k1_verify(&other_hash, &signature, &public_key),
+                Ok(false)
+            );
+        }
+    }
+
+    #[test]
+    fn secp256k1_rejects_malformed_inputs() {
+        let test = &secp256k1_tests()[0];
+        let message_hash = hex::decode(&test.message_hash).unwrap();
+        let signature = hex::decode(&test.signature).unwrap();
+        let public_key = hex::decode(&test.pubkey).unwrap();
+        let mut bad_prefix = public_key.clone();
+        bad_prefix[0] = 0x02;
+
+        assert_eq!(
+            secp256k1_verify(&message_hash[1..], &signature, &public_key),
+            Err(CryptoError::InvalidHashFormat)
+        );
+        assert_eq!(
+            secp256k1_verify(&message_hash, &signature[1..], &public_key),
+            Err(CryptoError::InvalidSignatureFormat)
+        );
+        assert_eq!(
+            secp256k1_verify(&message_hash, &signature, &public_key[1..]),
+            Err(CryptoError::InvalidPubkeyFormat)
+        );
+        assert_eq!(
+            secp256k1_verify(&message_hash, &signature, &bad_prefix),
+            Err(CryptoError::InvalidPubkeyFormat)
+        );
+        assert_eq!(
+            secp256k1_recover_pubkey(&message_hash[1..], &signature, 0),
+            Err(CryptoError::InvalidHashFormat)
+        );
+        assert_eq!(
+            secp256k1_recover_pubkey(&message_hash, &signature[1..], 0),
+            Err(CryptoError::InvalidSignatureFormat)
+        );
+    }
+
+    #[test]
+    fn secp256k1_recover_pubkey_works() {
+        // Every test signature recovers its public key with one of the two parities.
+        for (i, test) in secp256k1_tests().into_iter().enumerate() {
+            let message_hash = hex::decode(&test.message_hash).unwrap();
+            let signature = hex::decode(&test.signature).unwrap();
+            let public_key = hex::decode(&test.pubkey).unwrap();
+
+            let recovered = (0..=1)
+                .filter_map(|param| secp256k1_recover_pubkey(&message_hash, &signature, param).ok())
+                .collect::<Vec<_>>();
+
+            assert!(recovered.contains(&public_key), "test case {}", i);
+        }
+
+        // Test data from ethereumjs-util.
+        let private_key =
+            hex::decode("3c9229289a6125f7fdf1885a77bb12c37a8d3b4962d936f7e3084dece32a3ca1")
+                .unwrap();
+        let expected = SigningKey::from_slice(&private_key)
+            .unwrap()
+            .verifying_key()
+            .to_encoded_point(false)
+            .as_bytes()
+            .to_vec();
+        let r_s = hex::decode("99e71a99cb2270b8cac5254f9e99b6210c6c10224a1579cf389ef88b20a1abe9129ff05af364204442bdb53ab6f18a99ab48acc9326fa689f228040429e3ca66").unwrap();
+        let message_hash =
+            hex::decode("82ff40c0a986c6a5cfad4ddf4c3aa6996f1a7837f9c398e17e5de5cbd5a12b28")
+                .unwrap();
+        assert_eq!(
+            secp256k1_recover_pubkey(&message_hash, &r_s, 0),
+            Ok(expected)
+        );
+
+        // Test data from botan.
+        let expected = hex::decode("04F3F8BB913AA68589A2C8C607A877AB05252ADBD963E1BE846DDEB8456942AEDCA2ED51F08CA3EF3DAC0A7504613D54CD539FC1B3CBC92453CD704B6A2D012B2C").unwrap();
+        let r_s = hex::decode("E30F2E6A0F705F4FB5F8501BA79C7C0D3FAC847F1AD70B873E9797B17B89B39081F1A4457589F30D76AB9F89E748A68C8A94C30FE0BAC8FB5C0B54EA70BF6D2F").unwrap();
+        let message_hash = [0xFF; 32];
+        assert_eq!(
+            secp256k1_recover_pubkey(&message_hash, &r_s, 0),
+            Ok(expected)
+        );
+
+        // Test data from cosmjs.
+        let expected = hex::decode("044a071e8a6e10aada2b8cf39fa3b5fb3400b04e99ea8ae64ceea1a977dbeaf5d5f8c8fbd10b71ab14cd561f7df8eb6da50f8a8d81ba564342244d26d1d4211595").unwrap();
+        let r_s = hex::decode("45c0b7f8c09a9e1f1cea0c25785594427b6bf8f9f878a8af0b1abbb48e16d0920d8becd0c220f67c51217eecfd7184ef0732481c843857e6bc7fc095c4f6b788").unwrap();
+        let message_hash =
+            hex::decode("5ae8317d34d1e595e3fa7247db80c0af4320cce1116de187f8f7e2e099c0d8d0")
+                .unwrap();
+        assert_eq!(
+            secp256k1_recover_pubkey(&message_hash, &r_s, 1),
+            Ok(expected)
+        );
+        assert_eq!(
+            secp256k1_recover_pubkey(&message_hash, &r_s, 2),
+            Err(CryptoError::InvalidRecoveryParam)
+        );
+    }
+
+    #[test]
+    fn ed25519_verify_works() {
+        let (messages, signatures, public_keys) = ed25519_tests();
+        for (i, ((message, signature), public_key)) in messages
+            .iter()
+            .zip(&signatures)
+            .zip(&public_keys)
+            .enumerate()
+        {
+            assert_eq!(
+                ed25519_verify(message, signature, public_key),
+                Ok(true),
+                "test case {}",
+                i
+            );
+            let mut other_message = message.clone();
+            other_message.push(0);
+            assert_eq!(
+                ed25519_verify(&other_message, signature, public_key),
+                Ok(false)
+            );
+        }
+    }
+
+    #[test]
+    fn ed25519_verify_rejects_malformed_inputs() {
+        let (messages, signatures, public_keys) = ed25519_tests();
+
+        assert_eq!(
+            ed25519_verify(&messages[0], &signatures[0][1..], &public_keys[0]),
+            Err(CryptoError::InvalidSignatureFormat)
+        );
+        assert_eq!(
+            ed25519_verify(&messages[0], &signatures[0], &public_keys[0][1..]),
+            Err(CryptoError::InvalidPubkeyFormat)
+        );
+    }
+
+    #[test]
+    fn ed25519_batch_verify_works() {
+        let (messages, signatures, public_keys) = ed25519_tests();
+        let (messages, signatures, public_keys) =
+            (slices(&messages), slices(&signatures), slices(&public_keys));
+
+        assert_eq!(
+            ed25519_batch_verify(&messages, &signatures, &public_keys),
+            Ok(true)
+        );
+
+        let mut tampered = signatures.clone();
+        tampered.swap(0, 1);
+        assert_eq!(
+            ed25519_batch_verify(&messages, &tampered, &public_keys),
+            Ok(false)
+        );
+        tampered[2] = &tampered[2][1..];
+        assert_eq!(
+            ed25519_batch_verify(&messages, &tampered, &public_keys),
+            Err(CryptoError::InvalidSignatureFormat)
+        );
+
+        assert_eq!(ed25519_batch_verify(&[], &[], &[]), Ok(true));
+    }
+
+    #[test]
+    fn ed25519_batch_verify_one_message_or_one_public_key() {
+        let tests: Vec<Ed25519Test> = serde_json::from_str(ED25519_TESTS_JSON).unwrap();
+        let signing_keys = tests
+            .iter()
+            .map(|test| {
+                let private_key = hex::decode(&test.privkey).unwrap();
+                Ed25519SigningKey::try_from(private_key.as_slice()).unwrap()
+            })
+            .collect::<Vec<_>>();
+        let public_keys = signing_keys
+            .iter()
+            .map(|key| <[u8; 32]>::from(Ed25519VerificationKey::from(key)).to_vec())
+            .collect::<Vec<_>>();
+        let messages = [b"one".to_vec(), b"two".to_vec(), b"three".to_vec()];
+
+        // One message signed by every key.
+        let signatures = signing_keys
+            .iter()
+            .map(|key| <[u8; 64]>::from(key.sign(&messages[0])).to_vec())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ed25519_batch_verify(
+                &slices(&messages[..1]),
+                &slices(&signatures),
+                &slices(&public_keys)
+            ),
+            Ok(true)
+        );
+        assert_eq!(
+            ed25519_batch_verify_cost(&slices(&signatures), &slices(&public_keys)),
+            ED25519_BATCH_VERIFY_COST * signatures.len() as u64
+        );
+
+        // Every message signed by one key.
+        let signatures = messages
+            .iter()
+            .map(|message| <[u8; 64]>::from(signing_keys[0].sign(message)).to_vec())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ed25519_batch_verify(
+                &slices(&messages),
+                &slices(&signatures),
+                &slices(&public_keys[..1])
+            ),
+            Ok(true)
+        );
+        assert_eq!(
+            ed25519_batch_verify_cost(&slices(&signatures), &slices(&public_keys[..1])),
+            ED25519_BATCH_VERIFY_ONE_PUBKEY_COST * 3
+        );
+    }
+
+    #[test]
+    fn ed25519_batch_verify_rejects_other_shapes() {
+        let (messages, signatures, public_keys) = ed25519_tests();
+        let (messages, signatures, public_keys) =
+            (slices(&messages), slices(&signatures), slices(&public_keys));
+
+        assert_eq!(
+            ed25519_batch_verify(&messages[..2], &signatures, &public_keys),
+            Err(CryptoError::BatchErr)
+        );
+        assert_eq!(
+            ed25519_batch_verify(&messages[..1], &signatures[..2], &public_keys),
+            Err(CryptoError::BatchErr)
+        );
+    }
+}
@@ -3,4 +3,5 @@
 extern crate alloc;
 
 pub mod vm;
+pub mod crypto;
 pub mod bind;
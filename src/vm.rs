@@ -1,24 +1,42 @@
+use crate::crypto;
+use alloc::boxed::Box;
 use alloc::collections::BTreeMap;
+use alloc::rc::Rc;
 use alloc::string::{String, ToString};
 use alloc::vec::Vec;
 use alloc::{format, vec};
+use bech32::{FromBase32, ToBase32};
 use core::fmt::Display;
+use core::marker::PhantomData;
+use core::ops::Bound;
 use core::{num::NonZeroU32, str::FromStr};
 use cosmwasm_minimal_std::{
-    Addr, Binary, CanonicalAddr, Coin, ContractInfo, CosmwasmQueryResult, Empty, Env, Event,
-    MessageInfo, Order, QueryResult, SystemResult,
+    Addr, Binary, BlockInfo, CanonicalAddr, Coin, ContractInfo, CosmwasmQueryResult, Empty, Env,
+    Event, MessageInfo, Order, QueryResult, SystemResult, Timestamp, TransactionInfo,
 };
 use cosmwasm_vm::{executor::*, has::*, memory::*, system::*, transaction::*, vm::*};
 use cosmwasm_vm_wasmi::*;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use wasm_bindgen::convert::FromWasmAbi;
 use wasm_bindgen::describe::WasmDescribe;
-use wasm_instrument::gas_metering::Rules;
-use wasmi::CanResume;
+use wasm_instrument::gas_metering::{MemoryGrowCost, Rules};
+use wasm_instrument::parity_wasm::elements::{Instruction, Module};
+use wasmi::{CanResume, ExternVal, ModuleInstance, NopExternals};
 
 const CANONICAL_LENGTH: usize = 54;
 const SHUFFLES_ENCODE: usize = 18;
 const SHUFFLES_DECODE: usize = 2;
+/// Shortest input accepted by the mock canonicalization.
+const MOCK_MIN_LENGTH: usize = 3;
+
+const BECH32_ACCOUNT_LENGTH: usize = 20;
+const BECH32_CONTRACT_LENGTH: usize = 32;
+const MAX_SALT_LENGTH: usize = 64;
+
+const REQUIRED_EXPORTS: [&str; 3] = ["allocate", "deallocate", "interface_version_8"];
+
+pub const DEFAULT_GAS_LIMIT: u64 = 10_000_000_000_000;
 
 #[derive(Debug)]
 pub enum SimpleVMError {
@@ -26,17 +44,43 @@ pub enum SimpleVMError {
     VMError(WasmiVMError),
     CodeNotFound(CosmwasmCodeId),
     ContractNotFound(BankAccount),
+    ContractAlreadyExists(BankAccount),
     InvalidAddress,
     InvalidAccountFormat,
+    InvalidSalt,
     NoCustomQuery,
     NoCustomMessage,
     Unsupported,
+    Unauthorized,
     OutOfGas,
+    InsufficientFunds,
+    BalanceOverflow,
     IteratorDoesNotExist,
+    MemoryNotExported,
+    InstrumentationFailed,
+    InvalidCode,
+    MissingExport(String),
+    UnknownImport(String),
+    Crypto(crypto::CryptoError),
+    /// Error of the contract at the address, called by another contract.
+    InSubCall(BankAccount, Box<SimpleVMError>),
 }
 impl From<wasmi::Error> for SimpleVMError {
     fn from(e: wasmi::Error) -> Self {
-        Self::Interpreter(e)
+        // Errors of the host functions come back wrapped by the interpreter, unwrap them to
+        // keep their kind.
+        match e.try_into_host_error() {
+            Ok(host_error) => match host_error.downcast::<SimpleVMError>() {
+                Ok(error) => *error,
+                Err(host_error) => Self::Interpreter(wasmi::Error::Host(host_error)),
+            },
+            Err(e) => Self::Interpreter(e),
+        }
+    }
+}
+impl From<crypto::CryptoError> for SimpleVMError {
+    fn from(e: crypto::CryptoError) -> Self {
+        SimpleVMError::Crypto(e)
     }
 }
 impl From<WasmiVMError> for SimpleVMError {
@@ -66,7 +110,107 @@ impl From<MemoryWriteError> for SimpleVMError {
 }
 impl Display for SimpleVMError {
     fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
-        write!(f, "{:?}", self)
+        match self {
+            SimpleVMError::Interpreter(e) => write!(f, "interpreter error: {}", e),
+            SimpleVMError::VMError(WasmiVMError::SystemError(
+                SystemError::ContractExecutionFailure(message),
+            )) => write!(f, "contract execution failed: {}", message),
+            SimpleVMError::VMError(e) => write!(f, "vm error: {:?}", e),
+            SimpleVMError::CodeNotFound(code_id) => write!(f, "code {} not found", code_id),
+            SimpleVMError::ContractNotFound(address) => {
+                write!(f, "contract {} not found", address.0)
+            }
+            SimpleVMError::ContractAlreadyExists(address) => {
+                write!(f, "contract {} already exists", address.0)
+            }
+            SimpleVMError::InvalidAddress => write!(f, "invalid address"),
+            SimpleVMError::InvalidAccountFormat => write!(f, "invalid account format"),
+            SimpleVMError::InvalidSalt => write!(f, "salt must be 1 to 64 bytes long"),
+            SimpleVMError::NoCustomQuery => write!(f, "custom queries are not supported"),
+            SimpleVMError::NoCustomMessage => write!(f, "custom messages are not supported"),
+            SimpleVMError::Unsupported => write!(f, "unsupported operation"),
+            SimpleVMError::Unauthorized => write!(f, "unauthorized"),
+            SimpleVMError::OutOfGas => write!(f, "out of gas"),
+            SimpleVMError::InsufficientFunds => write!(f, "insufficient funds"),
+            SimpleVMError::BalanceOverflow => write!(f, "balance overflow"),
+            SimpleVMError::IteratorDoesNotExist => write!(f, "iterator does not exist"),
+            SimpleVMError::MemoryNotExported => write!(f, "contract does not export its memory"),
+            SimpleVMError::InstrumentationFailed => write!(f, "failed to instrument the code"),
+            SimpleVMError::InvalidCode => write!(f, "invalid wasm code"),
+            SimpleVMError::MissingExport(name) => write!(f, "missing required export {}", name),
+            SimpleVMError::UnknownImport(name) => write!(f, "unknown import {}", name),
+            SimpleVMError::Crypto(e) => write!(f, "{}", e),
+            SimpleVMError::InSubCall(_, error) => write!(f, "{}", error),
+        }
+    }
+}
+
+/// Category of a failure, letting the JS side tell errors apart without parsing messages.
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Eq, Debug)]
+pub enum SimpleVMErrorKind {
+    Interpreter,
+    VM,
+    ContractExecutionFailure,
+    /// The chain could not answer a query, e.g. an unknown contract or an unsupported request.
+    System,
+    CodeNotFound,
+    ContractNotFound,
+    ContractAlreadyExists,
+    InvalidAddress,
+    InvalidSalt,
+    Unsupported,
+    Unauthorized,
+    OutOfGas,
+    InsufficientFunds,
+    BalanceOverflow,
+    InvalidCode,
+    /// Malformed inputs of a signature verification or public key recovery, or a
+    /// mismatched ed25519 batch.
+    Crypto,
+    Deserialization,
+    Serialization,
+}
+
+impl SimpleVMError {
+    pub fn kind(&self) -> SimpleVMErrorKind {
+        match self {
+            SimpleVMError::Interpreter(_) => SimpleVMErrorKind::Interpreter,
+            SimpleVMError::VMError(WasmiVMError::SystemError(
+                SystemError::ContractExecutionFailure(_),
+            )) => SimpleVMErrorKind::ContractExecutionFailure,
+            SimpleVMError::VMError(_) | SimpleVMError::IteratorDoesNotExist => {
+                SimpleVMErrorKind::VM
+            }
+            SimpleVMError::CodeNotFound(_) => SimpleVMErrorKind::CodeNotFound,
+            SimpleVMError::ContractNotFound(_) => SimpleVMErrorKind::ContractNotFound,
+            SimpleVMError::ContractAlreadyExists(_) => SimpleVMErrorKind::ContractAlreadyExists,
+            SimpleVMError::InvalidSalt => SimpleVMErrorKind::InvalidSalt,
+            SimpleVMError::InvalidAddress | SimpleVMError::InvalidAccountFormat => {
+                SimpleVMErrorKind::InvalidAddress
+            }
+            SimpleVMError::NoCustomQuery
+            | SimpleVMError::NoCustomMessage
+            | SimpleVMError::Unsupported => SimpleVMErrorKind::Unsupported,
+            SimpleVMError::Unauthorized => SimpleVMErrorKind::Unauthorized,
+            SimpleVMError::OutOfGas => SimpleVMErrorKind::OutOfGas,
+            SimpleVMError::InsufficientFunds => SimpleVMErrorKind::InsufficientFunds,
+            SimpleVMError::BalanceOverflow => SimpleVMErrorKind::BalanceOverflow,
+            SimpleVMError::MemoryNotExported
+            | SimpleVMError::InstrumentationFailed
+            | SimpleVMError::InvalidCode
+            | SimpleVMError::MissingExport(_)
+            | SimpleVMError::UnknownImport(_) => SimpleVMErrorKind::InvalidCode,
+            SimpleVMError::Crypto(_) => SimpleVMErrorKind::Crypto,
+            SimpleVMError::InSubCall(_, error) => error.kind(),
+        }
+    }
+
+    /// Contract of the sub call the error was raised in, if any.
+    pub fn sub_call_address(&self) -> Option<&BankAccount> {
+        match self {
+            SimpleVMError::InSubCall(address, _) => Some(address),
+            _ => None,
+        }
     }
 }
 impl CanResume for SimpleVMError {
@@ -109,6 +253,11 @@ impl Gas {
             _ => Err(SimpleVMError::OutOfGas),
         }
     }
+    /// Gas left across all the checkpoints, including the ones a failed call
+    /// did not get to pop.
+    pub fn remaining(&self) -> u64 {
+        self.checkpoints.iter().sum()
+    }
     fn pop(&mut self) {
         let child = self.checkpoints.pop().expect("impossible");
         let parent = self.current_mut();
@@ -125,26 +274,781 @@ impl Gas {
     }
 }
 
-#[derive(Default, Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+/// The block the chain currently sits at, exposed to contracts through `Env`.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct Block {
+    pub height: u64,
+    /// Nanoseconds since the unix epoch.
+    pub time: u64,
+    pub chain_id: String,
+}
+
+impl Default for Block {
+    fn default() -> Self {
+        Block {
+            height: 0xDEADC0DE,
+            time: 0,
+            chain_id: "abstract-test".into(),
+        }
+    }
+}
+
+impl Block {
+    /// Move `blocks` blocks forward, each lasting `seconds_per_block`.
+    pub fn advance(&mut self, blocks: u64, seconds_per_block: u64) {
+        self.height = self.height.saturating_add(blocks);
+        self.time = self.time.saturating_add(
+            blocks
+                .saturating_mul(seconds_per_block)
+                .saturating_mul(1_000_000_000),
+        );
+    }
+    pub fn info(&self) -> BlockInfo {
+        BlockInfo {
+            height: self.height,
+            time: Timestamp(self.time),
+            chain_id: self.chain_id.clone(),
+        }
+    }
+}
+
+#[derive(Default, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
 pub struct Iter {
     data: Vec<(Vec<u8>, Vec<u8>)>,
     position: usize,
 }
 
+/// Iterators opened by a contract frame, they live as long as the call does.
+#[derive(Default, Clone, PartialEq, Eq, Debug)]
+pub struct SimpleWasmiVMIterators {
+    entries: BTreeMap<u32, Iter>,
+    last_id: u32,
+}
+
+impl SimpleWasmiVMIterators {
+    /// Open an iterator over `data`, ids are never reused within a call.
+    pub fn open(&mut self, data: Vec<(Vec<u8>, Vec<u8>)>) -> u32 {
+        // Exceeding u32 size is fatal
+        let new_id = self
+            .last_id
+            .checked_add(1)
+            .expect("Found more iterator IDs than supported");
+        self.last_id = new_id;
+        self.entries.insert(new_id, Iter { data, position: 0 });
+        new_id
+    }
+
+    pub fn next(&mut self, iterator_id: u32) -> Result<(Vec<u8>, Vec<u8>), SimpleVMError> {
+        let iterator = self
+            .entries
+            .get_mut(&iterator_id)
+            .ok_or(SimpleVMError::IteratorDoesNotExist)?;
+
+        let position = iterator.position;
+        if iterator.data.len() > position {
+            iterator.position += 1;
+            Ok(iterator.data[position].clone())
+        } else {
+            // Empty data works like `None` in rust iterators
+            Ok((Default::default(), Default::default()))
+        }
+    }
+}
+
 #[derive(Default, Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
 pub struct SimpleWasmiVMStorage {
-    data: BTreeMap<String, Vec<u8>>,
-    iterators: BTreeMap<u32, Iter>,
+    #[serde(with = "hex_keys")]
+    data: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+impl SimpleWasmiVMStorage {
+    /// Entries with a key within `[start, end)`, in the byte order of the keys.
+    pub fn range(
+        &self,
+        start: Option<Vec<u8>>,
+        end: Option<Vec<u8>>,
+        order: Order,
+    ) -> Vec<(Vec<u8>, Vec<u8>)> {
+        // `BTreeMap::range` panics on inverted bounds, the range is empty anyway.
+        if matches!((&start, &end), (Some(start), Some(end)) if start > end) {
+            return Vec::new();
+        }
+        let mut data = self
+            .data
+            .range((
+                start.map_or(Bound::Unbounded, Bound::Included),
+                end.map_or(Bound::Unbounded, Bound::Excluded),
+            ))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect::<Vec<_>>();
+        if let Order::Descending = order {
+            data.reverse();
+        }
+        data
+    }
+}
+
+/// Storage keys are raw bytes, but JSON objects only have string keys.
+mod hex_keys {
+    use alloc::collections::BTreeMap;
+    use alloc::string::String;
+    use alloc::vec::Vec;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        data: &BTreeMap<Vec<u8>, Vec<u8>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(data.iter().map(|(key, value)| (hex::encode(key), value)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<BTreeMap<Vec<u8>, Vec<u8>>, D::Error> {
+        BTreeMap::<String, Vec<u8>>::deserialize(deserializer)?
+            .into_iter()
+            .map(|(key, value)| Ok((hex::decode(key).map_err(D::Error::custom)?, value)))
+            .collect()
+    }
+}
+
+/// Balances are `u128`, which neither JSON nor JS numbers hold exactly, they are
+/// written as decimal strings like `Uint128` is.
+mod amounts {
+    use super::BankAccount;
+    use alloc::collections::BTreeMap;
+    use alloc::string::{String, ToString};
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    type Balances = BTreeMap<BankAccount, BTreeMap<String, u128>>;
+
+    pub fn serialize<S: Serializer>(balances: &Balances, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(balances.iter().map(|(account, balances)| {
+            let balances = balances
+                .iter()
+                .map(|(denom, amount)| (denom, amount.to_string()))
+                .collect::<BTreeMap<_, _>>();
+            (account, balances)
+        }))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Balances, D::Error> {
+        BTreeMap::<BankAccount, BTreeMap<String, String>>::deserialize(deserializer)?
+            .into_iter()
+            .map(|(account, balances)| {
+                let balances = balances
+                    .into_iter()
+                    .map(|(denom, amount)| Ok((denom, amount.parse().map_err(D::Error::custom)?)))
+                    .collect::<Result<_, D::Error>>()?;
+                Ok((account, balances))
+            })
+            .collect()
+    }
+}
+
+/// A single reversible mutation of the extension, holding the value that was
+/// present before the mutation happened.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum JournalEntry {
+    Storage {
+        address: BankAccount,
+        key: Vec<u8>,
+        previous: Option<Vec<u8>>,
+    },
+    Contract {
+        address: BankAccount,
+        previous: Option<CosmwasmContractMeta<BankAccount>>,
+    },
+    Creator {
+        address: BankAccount,
+        previous: Option<BankAccount>,
+    },
+    Code {
+        code_id: CosmwasmCodeId,
+        previous: Option<SimpleWasmiVMCode>,
+    },
+    NextAccountId(u64),
+    NextCodeId(CosmwasmCodeId),
+    Balance {
+        account: BankAccount,
+        denom: String,
+        previous: u128,
+    },
+}
+
+/// Undo log of the mutations done within the currently opened transactions.
+/// Every checkpoint is the length of the log when the transaction began.
+#[derive(Default, Clone, PartialEq, Eq, Debug)]
+pub struct Journal {
+    entries: Vec<JournalEntry>,
+    checkpoints: Vec<usize>,
+}
+
+/// Parsed and validated modules, keyed by code id along with the checksum of the
+/// code they were compiled from.
+#[derive(Default, Clone)]
+pub struct ModuleCache(BTreeMap<CosmwasmCodeId, (Vec<u8>, Rc<wasmi::Module>)>);
+
+impl ModuleCache {
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+
+    pub fn get_or_compile(
+        &mut self,
+        code_id: CosmwasmCodeId,
+        SimpleWasmiVMCode { code, checksum }: &SimpleWasmiVMCode,
+        rules: &GasRules,
+    ) -> Result<Rc<wasmi::Module>, SimpleVMError> {
+        match self.0.get(&code_id) {
+            Some((cached_checksum, module)) if cached_checksum == checksum => Ok(module.clone()),
+            _ => {
+                let module = Rc::new(wasmi::Module::from_buffer(instrument(code, rules)?)?);
+                self.0.insert(code_id, (checksum.clone(), module.clone()));
+                Ok(module)
+            }
+        }
+    }
+}
+
+// The cache is derived from the codes, it never makes two states different.
+impl PartialEq for ModuleCache {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for ModuleCache {}
+
+impl core::fmt::Debug for ModuleCache {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_set().entries(self.0.keys()).finish()
+    }
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum DebugMessageKind {
+    /// Emitted through `deps.api.debug`.
+    Debug,
+    /// The message of a contract panic.
+    Abort,
+}
+
+/// Output of a contract, tagged with the contract and how deep in the call stack it ran.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct DebugMessage {
+    pub kind: DebugMessageKind,
+    pub address: BankAccount,
+    /// `0` for the contract called at the top-level, `1` for its sub calls and so on.
+    pub depth: u32,
+    pub message: String,
+}
+
+/// Callback notified of every debug message as soon as it is emitted.
+#[derive(Default, Clone)]
+pub struct DebugHandler(pub Option<Rc<dyn Fn(&DebugMessage)>>);
+
+// Like the module cache, the handler is not part of the state.
+impl PartialEq for DebugHandler {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for DebugHandler {}
+
+impl core::fmt::Debug for DebugHandler {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_tuple("DebugHandler")
+            .field(&self.0.is_some())
+            .finish()
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct SimpleWasmiVMCode {
+    pub code: Vec<u8>,
+    /// Sha256 of the code.
+    #[serde(with = "hex::serde")]
+    pub checksum: Vec<u8>,
+}
+
+impl SimpleWasmiVMCode {
+    pub fn new(code: Vec<u8>) -> Self {
+        let checksum = Sha256::digest(&code).to_vec();
+        SimpleWasmiVMCode { code, checksum }
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
 pub struct SimpleWasmiVMExtension {
     pub storage: BTreeMap<BankAccount, SimpleWasmiVMStorage>,
-    pub codes: BTreeMap<CosmwasmCodeId, Vec<u8>>,
+    pub codes: BTreeMap<CosmwasmCodeId, SimpleWasmiVMCode>,
+    #[serde(default = "default_next_code_id")]
+    pub next_code_id: CosmwasmCodeId,
     pub contracts: BTreeMap<BankAccount, CosmwasmContractMeta<BankAccount>>,
-    pub next_account_id: BankAccount,
+    /// Account that instantiated each contract.
+    #[serde(default)]
+    pub creators: BTreeMap<BankAccount, BankAccount>,
+    /// Named accounts, e.g. `alice` or `treasury`.
+    #[serde(default)]
+    pub accounts: BTreeMap<String, BankAccount>,
+    /// Next id of wasmd's global contract instance sequence.
+    pub next_account_id: u64,
     pub transaction_depth: u32,
     pub gas: Gas,
+    #[serde(default = "default_gas_limit")]
+    pub gas_limit: u64,
+    #[serde(default, with = "amounts")]
+    pub balances: BTreeMap<BankAccount, BTreeMap<String, u128>>,
+    #[serde(default)]
+    pub gas_rules: GasRules,
+    #[serde(default)]
+    pub block: Block,
+    #[serde(default)]
+    pub address_mode: AddressMode,
+    /// Index of the next top-level call within the current block.
+    #[serde(default)]
+    pub transaction_index: u32,
+    #[serde(skip)]
+    pub journal: Journal,
+    #[serde(skip)]
+    pub modules: ModuleCache,
+    #[serde(skip)]
+    pub gas_breakdown: BTreeMap<&'static str, u64>,
+    /// Debug messages of the running call, kept even if the call fails.
+    #[serde(skip)]
+    pub debug_messages: Vec<DebugMessage>,
+    #[serde(skip)]
+    pub debug_handler: DebugHandler,
+}
+
+fn default_gas_limit() -> u64 {
+    DEFAULT_GAS_LIMIT
+}
+
+fn default_next_code_id() -> CosmwasmCodeId {
+    1
+}
+
+impl SimpleWasmiVMExtension {
+    pub fn new(gas_limit: u64, address_mode: AddressMode) -> Self {
+        SimpleWasmiVMExtension {
+            storage: Default::default(),
+            codes: Default::default(),
+            next_code_id: default_next_code_id(),
+            contracts: Default::default(),
+            creators: Default::default(),
+            accounts: Default::default(),
+            next_account_id: 1,
+            transaction_depth: 0,
+            gas: Gas::new(gas_limit),
+            gas_limit,
+            balances: Default::default(),
+            gas_rules: Default::default(),
+            block: Default::default(),
+            address_mode,
+            transaction_index: 0,
+            journal: Default::default(),
+            modules: Default::default(),
+            gas_breakdown: Default::default(),
+            debug_messages: Default::default(),
+            debug_handler: Default::default(),
+        }
+    }
+
+    pub fn debug(&mut self, message: DebugMessage) {
+        if let Some(handler) = &self.debug_handler.0 {
+            handler(&message);
+        }
+        self.debug_messages.push(message);
+    }
+
+    pub fn advance_blocks(&mut self, blocks: u64, seconds_per_block: u64) {
+        self.block.advance(blocks, seconds_per_block);
+        self.transaction_index = 0;
+    }
+
+    pub fn set_block_height(&mut self, height: u64) {
+        self.block.height = height;
+        self.transaction_index = 0;
+    }
+
+    pub fn set_block_time(&mut self, time: u64) {
+        self.block.time = time;
+        self.transaction_index = 0;
+    }
+
+    pub fn transaction_info(&self) -> TransactionInfo {
+        TransactionInfo {
+            index: self.transaction_index,
+        }
+    }
+
+    /// Refill the gas up to the limit, starting a new call.
+    pub fn gas_reset(&mut self) {
+        self.gas = Gas::new(self.gas_limit);
+        self.gas_breakdown.clear();
+    }
+
+    /// Charge `value` gas, accounted under `category` in the breakdown.
+    pub fn charge(&mut self, category: &'static str, value: u64) -> Result<(), SimpleVMError> {
+        self.gas.charge(value)?;
+        *self.gas_breakdown.entry(category).or_default() += value;
+        Ok(())
+    }
+
+    fn record(&mut self, entry: JournalEntry) {
+        // Outside of a transaction there is nothing to rollback to.
+        if !self.journal.checkpoints.is_empty() {
+            self.journal.entries.push(entry);
+        }
+    }
+
+    fn undo(&mut self, entry: JournalEntry) {
+        match entry {
+            JournalEntry::Storage {
+                address,
+                key,
+                previous,
+            } => {
+                let storage = self.storage.entry(address).or_default();
+                match previous {
+                    Some(value) => storage.data.insert(key, value),
+                    None => storage.data.remove(&key),
+                };
+            }
+            JournalEntry::Contract { address, previous } => {
+                match previous {
+                    Some(meta) => self.contracts.insert(address, meta),
+                    None => self.contracts.remove(&address),
+                };
+            }
+            JournalEntry::Creator { address, previous } => {
+                match previous {
+                    Some(creator) => self.creators.insert(address, creator),
+                    None => self.creators.remove(&address),
+                };
+            }
+            JournalEntry::Code { code_id, previous } => {
+                match previous {
+                    Some(code) => self.codes.insert(code_id, code),
+                    None => self.codes.remove(&code_id),
+                };
+            }
+            JournalEntry::NextAccountId(previous) => {
+                self.next_account_id = previous;
+            }
+            JournalEntry::NextCodeId(previous) => {
+                self.next_code_id = previous;
+            }
+            JournalEntry::Balance {
+                account,
+                denom,
+                previous,
+            } => {
+                self.write_balance(account, denom, previous);
+            }
+        }
+    }
+
+    fn write_balance(&mut self, account: BankAccount, denom: String, amount: u128) -> u128 {
+        let balances = self.balances.entry(account.clone()).or_default();
+        let previous = if amount == 0 {
+            balances.remove(&denom)
+        } else {
+            balances.insert(denom, amount)
+        };
+        if balances.is_empty() {
+            self.balances.remove(&account);
+        }
+        previous.unwrap_or_default()
+    }
+
+    pub fn set_storage(&mut self, address: BankAccount, key: Vec<u8>, value: Option<Vec<u8>>) {
+        let storage = self.storage.entry(address.clone()).or_default();
+        let previous = match value {
+            Some(value) => storage.data.insert(key.clone(), value),
+            None => storage.data.remove(&key),
+        };
+        self.record(JournalEntry::Storage {
+            address,
+            key,
+            previous,
+        });
+    }
+
+    pub fn set_contract(
+        &mut self,
+        address: BankAccount,
+        meta: Option<CosmwasmContractMeta<BankAccount>>,
+    ) {
+        let previous = match meta {
+            Some(meta) => self.contracts.insert(address.clone(), meta),
+            None => self.contracts.remove(&address),
+        };
+        self.record(JournalEntry::Contract { address, previous });
+    }
+
+    /// Replace the meta of the contract at `address` on behalf of `sender`, which
+    /// must be the current admin of the contract.
+    pub fn update_contract_meta(
+        &mut self,
+        sender: BankAccount,
+        address: BankAccount,
+        meta: CosmwasmContractMeta<BankAccount>,
+    ) -> Result<(), SimpleVMError> {
+        let current = self
+            .contracts
+            .get(&address)
+            .ok_or_else(|| SimpleVMError::ContractNotFound(address.clone()))?;
+        if current.admin.as_ref() != Some(&sender) {
+            return Err(SimpleVMError::Unauthorized);
+        }
+        if !self.codes.contains_key(&meta.code_id) {
+            return Err(SimpleVMError::CodeNotFound(meta.code_id));
+        }
+        self.set_contract(address, Some(meta));
+        Ok(())
+    }
+
+    /// Replace, or clear if `admin` is `None`, the admin of the contract at `address`
+    /// on behalf of `sender`, which must be the current admin.
+    pub fn update_contract_admin(
+        &mut self,
+        sender: BankAccount,
+        address: BankAccount,
+        admin: Option<BankAccount>,
+    ) -> Result<(), SimpleVMError> {
+        let meta = self
+            .contracts
+            .get(&address)
+            .cloned()
+            .ok_or_else(|| SimpleVMError::ContractNotFound(address.clone()))?;
+        self.update_contract_meta(sender, address, CosmwasmContractMeta { admin, ..meta })
+    }
+
+    pub fn set_code(&mut self, code_id: CosmwasmCodeId, code: Option<SimpleWasmiVMCode>) {
+        let previous = match code {
+            Some(code) => self.codes.insert(code_id, code),
+            None => self.codes.remove(&code_id),
+        };
+        self.record(JournalEntry::Code { code_id, previous });
+    }
+
+    /// Validate `code` and store it under a newly allocated code id.
+    pub fn store_code(&mut self, code: Vec<u8>) -> Result<CosmwasmCodeId, SimpleVMError> {
+        validate(&code)?;
+        let code_id = self.next_code_id;
+        self.record(JournalEntry::NextCodeId(code_id));
+        self.next_code_id = code_id + 1;
+        self.set_code(code_id, Some(SimpleWasmiVMCode::new(code)));
+        Ok(code_id)
+    }
+
+    pub fn contract_module(
+        &mut self,
+        address: BankAccount,
+    ) -> Result<Rc<wasmi::Module>, SimpleVMError> {
+        let CosmwasmContractMeta { code_id, .. } = self
+            .contracts
+            .get(&address)
+            .ok_or(SimpleVMError::ContractNotFound(address))?;
+        let code = self
+            .codes
+            .get(code_id)
+            .ok_or(SimpleVMError::CodeNotFound(*code_id))?;
+        self.modules.get_or_compile(*code_id, code, &self.gas_rules)
+    }
+
+    pub fn set_gas_rules(&mut self, gas_rules: GasRules) {
+        self.gas_rules = gas_rules;
+        // Cached modules have been instrumented with the previous rules.
+        self.modules.clear();
+    }
+
+    /// Allocate the address of a new contract and register its meta and creator.
+    /// Like on wasmd, the address is derived from the code id and a global instance
+    /// sequence, or predictably from the code checksum, the creator and `salt` if any.
+    pub fn create_contract(
+        &mut self,
+        creator: BankAccount,
+        meta: CosmwasmContractMeta<BankAccount>,
+        salt: Option<&[u8]>,
+    ) -> Result<BankAccount, SimpleVMError> {
+        let code = self
+            .codes
+            .get(&meta.code_id)
+            .ok_or(SimpleVMError::CodeNotFound(meta.code_id))?;
+        let address = match salt {
+            Some(salt) => {
+                self.address_mode
+                    .predictable_contract_address(&code.checksum, &creator, salt)?
+            }
+            None => self
+                .address_mode
+                .contract_address(meta.code_id, self.next_account_id)?,
+        };
+        if self.contracts.contains_key(&address) {
+            return Err(SimpleVMError::ContractAlreadyExists(address));
+        }
+        if salt.is_none() {
+            // Only taken once the address is known to be free.
+            self.next_instance_id();
+        }
+        self.set_contract(address.clone(), Some(meta));
+        let previous = self.creators.insert(address.clone(), creator);
+        self.record(JournalEntry::Creator {
+            address: address.clone(),
+            previous,
+        });
+        Ok(address)
+    }
+
+    fn next_instance_id(&mut self) -> u64 {
+        let id = self.next_account_id;
+        self.record(JournalEntry::NextAccountId(id));
+        self.next_account_id = id + 1;
+        id
+    }
+
+    pub fn balance(&self, account: &BankAccount, denom: &str) -> u128 {
+        self.balances
+            .get(account)
+            .and_then(|balances| balances.get(denom))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn all_balance(&self, account: &BankAccount) -> Vec<Coin> {
+        self.balances
+            .get(account)
+            .map(|balances| {
+                balances
+                    .iter()
+                    .map(|(denom, amount)| Coin {
+                        denom: denom.clone(),
+                        amount: (*amount).into(),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn set_balance(&mut self, account: BankAccount, denom: String, amount: u128) {
+        let previous = self.write_balance(account.clone(), denom.clone(), amount);
+        self.record(JournalEntry::Balance {
+            account,
+            denom,
+            previous,
+        });
+    }
+
+    /// The account registered under `name`, created if it does not exist yet, and
+    /// funded with `funds` in both cases.
+    pub fn named_account(
+        &mut self,
+        name: &str,
+        funds: &[Coin],
+    ) -> Result<BankAccount, SimpleVMError> {
+        let account = match self.accounts.get(name) {
+            Some(account) => account.clone(),
+            None => self.address_mode.named_account(name)?,
+        };
+        self.mint(account.clone(), funds)?;
+        self.accounts.insert(name.into(), account.clone());
+        Ok(account)
+    }
+
+    pub fn mint(&mut self, account: BankAccount, funds: &[Coin]) -> Result<(), SimpleVMError> {
+        self.atomically(|extension| {
+            for Coin { denom, amount } in funds {
+                let amount = u128::from(*amount);
+                // Like the bank module, zero amounts move nothing.
+                if amount == 0 {
+                    continue;
+                }
+                let balance = extension
+                    .balance(&account, denom)
+                    .checked_add(amount)
+                    .ok_or(SimpleVMError::BalanceOverflow)?;
+                extension.set_balance(account.clone(), denom.clone(), balance);
+            }
+            Ok(())
+        })
+    }
+
+    pub fn burn(&mut self, account: BankAccount, funds: &[Coin]) -> Result<(), SimpleVMError> {
+        self.atomically(|extension| {
+            for Coin { denom, amount } in funds {
+                let amount = u128::from(*amount);
+                // Like the bank module, zero amounts move nothing.
+                if amount == 0 {
+                    continue;
+                }
+                let balance = extension
+                    .balance(&account, denom)
+                    .checked_sub(amount)
+                    .ok_or(SimpleVMError::InsufficientFunds)?;
+                extension.set_balance(account.clone(), denom.clone(), balance);
+            }
+            Ok(())
+        })
+    }
+
+    pub fn transfer(
+        &mut self,
+        from: BankAccount,
+        to: BankAccount,
+        funds: &[Coin],
+    ) -> Result<(), SimpleVMError> {
+        self.atomically(|extension| {
+            extension.burn(from, funds)?;
+            extension.mint(to, funds)
+        })
+    }
+
+    /// Run `f` within its own transaction, reverting its partial mutations on failure.
+    pub fn atomically<T>(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> Result<T, SimpleVMError>,
+    ) -> Result<T, SimpleVMError> {
+        self.transaction_begin();
+        let result = f(self);
+        match result {
+            Ok(_) => self.transaction_commit(),
+            Err(_) => self.transaction_rollback(),
+        }
+        result
+    }
+
+    pub fn transaction_begin(&mut self) {
+        self.transaction_depth += 1;
+        self.journal.checkpoints.push(self.journal.entries.len());
+    }
+
+    pub fn transaction_commit(&mut self) {
+        self.transaction_depth -= 1;
+        self.journal.checkpoints.pop();
+        // Once the outermost transaction is committed, the mutations are final.
+        if self.journal.checkpoints.is_empty() {
+            self.journal.entries.clear();
+        }
+    }
+
+    pub fn transaction_rollback(&mut self) {
+        self.transaction_depth -= 1;
+        let checkpoint = self
+            .journal
+            .checkpoints
+            .pop()
+            .expect("rollback without a transaction, this should never happen");
+        while self.journal.entries.len() > checkpoint {
+            let entry = self.journal.entries.pop().expect("impossible");
+            self.undo(entry);
+        }
+    }
 }
 
 pub struct SimpleWasmiVM<'a> {
@@ -152,6 +1056,12 @@ pub struct SimpleWasmiVM<'a> {
     pub executing_module: WasmiModule,
     pub env: Env,
     pub info: MessageInfo,
+    pub iterators: SimpleWasmiVMIterators,
+    /// Number of contracts calling into this one.
+    pub depth: u32,
+    /// Admin changes made by the contract, raised outside of any event handler and
+    /// forwarded at its next sub call or once it returns.
+    pub admin_events: Vec<Event>,
     pub extension: &'a mut SimpleWasmiVMExtension,
 }
 
@@ -191,50 +1101,112 @@ impl<'a> WritableMemory for SimpleWasmiVM<'a> {
 impl<'a> ReadWriteMemory for SimpleWasmiVM<'a> {}
 
 impl<'a> SimpleWasmiVM<'a> {
-    fn load_subvm<R>(
-        &mut self,
-        address: <Self as VMBase>::Address,
-        funds: Vec<Coin>,
-        f: impl FnOnce(&mut WasmiVM<SimpleWasmiVM>) -> R,
-    ) -> Result<R, VmErrorOf<Self>> {
-        let code = (|| {
-            let CosmwasmContractMeta { code_id, .. } = self
-                .extension
-                .contracts
-                .get(&address)
-                .cloned()
-                .ok_or(SimpleVMError::ContractNotFound(address))?;
-            self.extension
-                .codes
-                .get(&code_id)
-                .ok_or(SimpleVMError::CodeNotFound(code_id))
-                .cloned()
-        })()?;
+    /// Instantiate a fresh instance of the contract at `env.contract.address`,
+    /// reusing the cached module of its code.
+    pub fn new(
+        extension: &'a mut SimpleWasmiVMExtension,
+        depth: u32,
+        env: Env,
+        info: MessageInfo,
+    ) -> Result<WasmiVM<Self>, SimpleVMError> {
+        let module = extension.contract_module(env.contract.address.clone().try_into()?)?;
         let host_functions_definitions =
             WasmiImportResolver(host_functions::definitions::<SimpleWasmiVM>());
-        let module = new_wasmi_vm(&host_functions_definitions, &code)?;
-        let mut sub_vm: WasmiVM<SimpleWasmiVM> = WasmiVM(SimpleWasmiVM {
+        let instance = ModuleInstance::new(&module, &host_functions_definitions)?
+            .run_start(&mut NopExternals)
+            .map_err(wasmi::Error::from)?;
+        let memory = match instance.export_by_name("memory") {
+            Some(ExternVal::Memory(memory)) => memory,
+            _ => return Err(SimpleVMError::MemoryNotExported),
+        };
+        Ok(WasmiVM(SimpleWasmiVM {
             host_functions: host_functions_definitions
                 .0
                 .into_iter()
                 .flat_map(|(_, modules)| modules.into_iter().map(|(_, function)| function))
                 .collect(),
-            executing_module: module,
-            env: Env {
+            executing_module: WasmiModule {
+                module: instance,
+                memory,
+            },
+            env,
+            info,
+            iterators: Default::default(),
+            depth,
+            admin_events: Default::default(),
+            extension,
+        }))
+    }
+
+    fn emit_debug(&mut self, kind: DebugMessageKind, message: String) -> Result<(), SimpleVMError> {
+        let address = BankAccount::try_from(self.env.contract.address.clone())?;
+        self.extension.debug(DebugMessage {
+            kind,
+            address,
+            depth: self.depth,
+            message,
+        });
+        Ok(())
+    }
+
+    /// Reject addresses handed over by a contract that are not normalized for the chain.
+    fn validate_account(&self, account: &BankAccount) -> Result<(), SimpleVMError> {
+        self.extension.address_mode.validate(account)
+    }
+
+    fn load_subvm<R>(
+        &mut self,
+        address: <Self as VMBase>::Address,
+        funds: Vec<Coin>,
+        f: impl FnOnce(&mut WasmiVM<SimpleWasmiVM>) -> R,
+    ) -> Result<R, VmErrorOf<Self>> {
+        let sender = BankAccount::try_from(self.env.contract.address.clone())?;
+        self.extension.transfer(sender, address.clone(), &funds)?;
+        let mut sub_vm = SimpleWasmiVM::new(
+            self.extension,
+            self.depth + 1,
+            Env {
                 block: self.env.block.clone(),
                 transaction: self.env.transaction.clone(),
                 contract: ContractInfo {
                     address: address.into(),
                 },
             },
-            info: MessageInfo {
+            MessageInfo {
                 sender: self.env.contract.address.clone(),
                 funds,
             },
-            extension: self.extension,
-        });
+        )?;
         Ok(f(&mut sub_vm))
     }
+
+    /// Run a sub call, forwarding its events only once it succeeded: like on wasmd,
+    /// the events of a failed sub message are discarded along with its state and
+    /// only the error reaches the `reply` of the caller.
+    fn run_subvm<T>(
+        &mut self,
+        address: <Self as VMBase>::Address,
+        funds: Vec<Coin>,
+        event_handler: &mut dyn FnMut(Event),
+        f: impl FnOnce(&mut WasmiVM<SimpleWasmiVM>, &mut dyn FnMut(Event)) -> Result<T, VmErrorOf<Self>>,
+    ) -> Result<T, VmErrorOf<Self>> {
+        // Admin changes of the caller precede this sub call, they are committed already.
+        self.admin_events.drain(..).for_each(&mut *event_handler);
+        let mut events = Vec::new();
+        let result = self.load_subvm(address.clone(), funds, |sub_vm| {
+            let result = f(sub_vm, &mut |event| events.push(event));
+            events.append(&mut sub_vm.0.admin_events);
+            // Errors of deeper sub calls already carry the contract they failed in.
+            result.map_err(|e| match e {
+                SimpleVMError::InSubCall(..) => e,
+                e => SimpleVMError::InSubCall(address, Box::new(e)),
+            })
+        })?;
+        if result.is_ok() {
+            events.into_iter().for_each(event_handler);
+        }
+        result
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
@@ -288,23 +1260,37 @@ impl<'a> VMBase for SimpleWasmiVM<'a> {
         address: Self::Address,
         contract_meta: Self::ContractMeta,
     ) -> Result<(), Self::Error> {
-        let meta = self
+        // `WasmMsg::UpdateAdmin`, `ClearAdmin` and `Migrate` of the running contract land
+        // here, the contract must be the admin of the target. No event handler is given
+        // to this call, the `update_contract_admin` event is kept until the next sub call
+        // or the end of the contract call. It may hence come after the events of a
+        // `reply` that wasmd would have emitted later.
+        let sender = self.env.contract.address.clone().try_into()?;
+        self.validate_account(&address)?;
+        if let Some(admin) = &contract_meta.admin {
+            self.validate_account(admin)?;
+        }
+        let previous_admin = self
             .extension
             .contracts
-            .get_mut(&address)
-            .ok_or(SimpleVMError::ContractNotFound(address))?;
-
-        *meta = contract_meta;
-
+            .get(&address)
+            .and_then(|meta| meta.admin.clone());
+        let admin = contract_meta.admin.clone();
+        self.extension
+            .update_contract_meta(sender, address.clone(), contract_meta)?;
+        if admin != previous_admin {
+            self.admin_events
+                .push(update_admin_event(&address, admin.as_ref()));
+        }
         Ok(())
     }
 
     fn contract_meta(&mut self, address: Self::Address) -> Result<Self::ContractMeta, Self::Error> {
         self.extension
             .contracts
-            .get_mut(&address)
-            .ok_or(SimpleVMError::ContractNotFound(address))
+            .get(&address)
             .cloned()
+            .ok_or(SimpleVMError::ContractNotFound(address))
     }
 
     fn query_continuation(
@@ -312,6 +1298,7 @@ impl<'a> VMBase for SimpleWasmiVM<'a> {
         address: Self::Address,
         message: &[u8],
     ) -> Result<QueryResult, Self::Error> {
+        self.validate_account(&address)?;
         self.load_subvm(address, vec![], |sub_vm| {
             cosmwasm_call::<QueryInput, WasmiVM<SimpleWasmiVM>>(sub_vm, message)
         })?
@@ -324,13 +1311,14 @@ impl<'a> VMBase for SimpleWasmiVM<'a> {
         message: &[u8],
         event_handler: &mut dyn FnMut(Event),
     ) -> Result<Option<Binary>, Self::Error> {
-        self.load_subvm(address, funds, |sub_vm| {
+        self.validate_account(&address)?;
+        self.run_subvm(address, funds, event_handler, |sub_vm, event_handler| {
             cosmwasm_system_run::<ExecuteInput<Self::MessageCustom>, _>(
                 sub_vm,
                 message,
                 event_handler,
             )
-        })?
+        })
     }
 
     fn continue_instantiate(
@@ -340,20 +1328,29 @@ impl<'a> VMBase for SimpleWasmiVM<'a> {
         message: &[u8],
         event_handler: &mut dyn FnMut(Event),
     ) -> Result<(Self::Address, Option<Binary>), Self::Error> {
-        let BankAccount(address) = self.extension.next_account_id;
-        self.extension.next_account_id = BankAccount(address + 1);
-        self.extension
-            .contracts
-            .insert(BankAccount(address), contract_meta);
-
-        self.load_subvm(BankAccount(address), funds, |sub_vm| {
-            cosmwasm_system_run::<InstantiateInput<Self::MessageCustom>, _>(
-                sub_vm,
-                message,
-                event_handler,
-            )
-        })?
-        .map(|data| (BankAccount(address), data))
+        let creator = BankAccount::try_from(self.env.contract.address.clone())?;
+        if let Some(admin) = &contract_meta.admin {
+            self.validate_account(admin)?;
+        }
+        // The pinned cosmwasm-vm has no `WasmMsg::Instantiate2` and gives no salt here,
+        // contracts can only create contracts at classic addresses.
+        let address = self
+            .extension
+            .create_contract(creator, contract_meta, None)?;
+
+        self.run_subvm(
+            address.clone(),
+            funds,
+            event_handler,
+            |sub_vm, event_handler| {
+                cosmwasm_system_run::<InstantiateInput<Self::MessageCustom>, _>(
+                    sub_vm,
+                    message,
+                    event_handler,
+                )
+            },
+        )
+        .map(|data| (address, data))
     }
 
     fn continue_migrate(
@@ -362,13 +1359,14 @@ impl<'a> VMBase for SimpleWasmiVM<'a> {
         message: &[u8],
         event_handler: &mut dyn FnMut(Event),
     ) -> Result<Option<Binary>, Self::Error> {
-        self.load_subvm(address, vec![], |sub_vm| {
+        self.validate_account(&address)?;
+        self.run_subvm(address, vec![], event_handler, |sub_vm, event_handler| {
             cosmwasm_system_run::<MigrateInput<Self::MessageCustom>, _>(
                 sub_vm,
                 message,
                 event_handler,
             )
-        })?
+        })
     }
 
     fn query_custom(
@@ -397,90 +1395,85 @@ impl<'a> VMBase for SimpleWasmiVM<'a> {
             .get(&address)
             .unwrap_or(&Default::default())
             .data
-            .get(&hex::encode(key))
+            .get(&key)
             .cloned())
     }
 
-    fn transfer(&mut self, _to: &Self::Address, _funds: &[Coin]) -> Result<(), Self::Error> {
-        Ok(())
+    fn transfer(&mut self, to: &Self::Address, funds: &[Coin]) -> Result<(), Self::Error> {
+        let contract_addr = self.env.contract.address.clone().try_into()?;
+        self.validate_account(to)?;
+        self.extension.transfer(contract_addr, to.clone(), funds)
     }
 
-    fn burn(&mut self, _funds: &[Coin]) -> Result<(), Self::Error> {
-        Ok(())
+    fn burn(&mut self, funds: &[Coin]) -> Result<(), Self::Error> {
+        let contract_addr = self.env.contract.address.clone().try_into()?;
+        self.extension.burn(contract_addr, funds)
     }
 
-    fn balance(&mut self, _: &Self::Address, _: String) -> Result<Coin, Self::Error> {
-        Err(SimpleVMError::Unsupported)
+    fn balance(&mut self, account: &Self::Address, denom: String) -> Result<Coin, Self::Error> {
+        let amount = self.extension.balance(account, &denom);
+        Ok(Coin {
+            denom,
+            amount: amount.into(),
+        })
     }
 
-    fn all_balance(&mut self, _: &Self::Address) -> Result<Vec<Coin>, Self::Error> {
-        Ok(vec![])
+    fn all_balance(&mut self, account: &Self::Address) -> Result<Vec<Coin>, Self::Error> {
+        Ok(self.extension.all_balance(account))
     }
 
     fn query_info(
         &mut self,
-        _: Self::Address,
+        address: Self::Address,
     ) -> Result<cosmwasm_minimal_std::ContractInfoResponse, Self::Error> {
-        Err(SimpleVMError::Unsupported)
-    }
-
-    fn debug(&mut self, _message: Vec<u8>) -> Result<(), Self::Error> {
-        Ok(())
+        let CosmwasmContractMeta { code_id, admin, .. } = self
+            .extension
+            .contracts
+            .get(&address)
+            .cloned()
+            .ok_or_else(|| SimpleVMError::ContractNotFound(address.clone()))?;
+        Ok(cosmwasm_minimal_std::ContractInfoResponse {
+            code_id,
+            // Contracts imported along with a state may not have a known creator.
+            creator: self
+                .extension
+                .creators
+                .get(&address)
+                .map(|creator| Addr::from(creator.clone()).to_string())
+                .unwrap_or_default(),
+            admin: admin.map(|admin| Addr::from(admin).to_string()),
+            // Codes are never pinned and IBC is not supported.
+            pinned: false,
+            ibc_port: None,
+        })
+    }
+
+    fn debug(&mut self, message: Vec<u8>) -> Result<(), Self::Error> {
+        self.emit_debug(
+            DebugMessageKind::Debug,
+            String::from_utf8_lossy(&message).into_owned(),
+        )
     }
 
     fn db_scan(
         &mut self,
-        _start: Option<Self::StorageKey>,
-        _end: Option<Self::StorageKey>,
-        _order: Order,
+        start: Option<Self::StorageKey>,
+        end: Option<Self::StorageKey>,
+        order: Order,
     ) -> Result<u32, Self::Error> {
         let contract_addr = self.env.contract.address.clone().try_into()?;
-        let mut empty = SimpleWasmiVMStorage::default();
-        let storage = self
-            .extension
-            .storage
-            .get_mut(&contract_addr)
-            .unwrap_or(&mut empty);
-
-        let data = storage.data.clone().into_iter().map(|(x, y)| (hex::decode(x).unwrap(), y)).collect::<Vec<_>>();
-        // Exceeding u32 size is fatal
-        let last_id: u32 = storage
-            .iterators
-            .len()
-            .try_into()
-            .expect("Found more iterator IDs than supported");
-
-        let new_id = last_id + 1;
-        let iter = Iter { data, position: 0 };
-        storage.iterators.insert(new_id, iter);
+        let empty = SimpleWasmiVMStorage::default();
+        let storage = self.extension.storage.get(&contract_addr).unwrap_or(&empty);
 
-        Ok(new_id)
+        let data = storage.range(start, end, order);
+        Ok(self.iterators.open(data))
     }
 
     fn db_next(
         &mut self,
         iterator_id: u32,
     ) -> Result<(Self::StorageKey, Self::StorageValue), Self::Error> {
-        let contract_addr = self.env.contract.address.clone().try_into()?;
-        let storage = self
-            .extension
-            .storage
-            .get_mut(&contract_addr)
-            .ok_or(SimpleVMError::IteratorDoesNotExist)?;
-
-        let iterator = storage
-            .iterators
-            .get_mut(&iterator_id)
-            .ok_or(SimpleVMError::IteratorDoesNotExist)?;
-
-        let position = iterator.position;
-        if iterator.data.len() > position {
-            iterator.position += 1;
-            Ok(iterator.data[position].clone())
-        } else {
-            // Empty data works like `None` in rust iterators
-            Ok((Default::default(), Default::default()))
-        }
+        self.iterators.next(iterator_id)
     }
 
     fn secp256k1_verify(
@@ -489,7 +1482,13 @@ impl<'a> VMBase for SimpleWasmiVM<'a> {
         signature: &[u8],
         public_key: &[u8],
     ) -> Result<bool, Self::Error> {
-        unimplemented!()
+        self.extension
+            .charge("secp256k1_verify", crypto::SECP256K1_VERIFY_COST)?;
+        Ok(crypto::secp256k1_verify(
+            message_hash,
+            signature,
+            public_key,
+        )?)
     }
 
     fn secp256k1_recover_pubkey(
@@ -498,7 +1497,16 @@ impl<'a> VMBase for SimpleWasmiVM<'a> {
         signature: &[u8],
         recovery_param: u8,
     ) -> Result<Result<Vec<u8>, ()>, Self::Error> {
-        unimplemented!()
+        self.extension.charge(
+            "secp256k1_recover_pubkey",
+            crypto::SECP256K1_RECOVER_PUBKEY_COST,
+        )?;
+        // Only a failed recovery is left to the contract, malformed inputs fail the call.
+        match crypto::secp256k1_recover_pubkey(message_hash, signature, recovery_param) {
+            Ok(public_key) => Ok(Ok(public_key)),
+            Err(crypto::CryptoError::GenericErr) => Ok(Err(())),
+            Err(e) => Err(e.into()),
+        }
     }
 
     fn ed25519_verify(
@@ -507,7 +1515,9 @@ impl<'a> VMBase for SimpleWasmiVM<'a> {
         signature: &[u8],
         public_key: &[u8],
     ) -> Result<bool, Self::Error> {
-        unimplemented!()
+        self.extension
+            .charge("ed25519_verify", crypto::ED25519_VERIFY_COST)?;
+        Ok(crypto::ed25519_verify(message, signature, public_key)?)
     }
 
     fn ed25519_batch_verify(
@@ -516,81 +1526,41 @@ impl<'a> VMBase for SimpleWasmiVM<'a> {
         signatures: &[&[u8]],
         public_keys: &[&[u8]],
     ) -> Result<bool, Self::Error> {
-        unimplemented!()
+        self.extension.charge(
+            "ed25519_batch_verify",
+            crypto::ed25519_batch_verify_cost(signatures, public_keys),
+        )?;
+        Ok(crypto::ed25519_batch_verify(
+            messages,
+            signatures,
+            public_keys,
+        )?)
     }
 
     fn addr_validate(&mut self, input: &str) -> Result<Result<(), Self::Error>, Self::Error> {
-        let canonical = match self.addr_canonicalize(input)? {
-            Ok(canonical) => canonical,
-            Err(e) => return Ok(Err(e)),
-        };
-        let normalized = match self.addr_humanize(&canonical)? {
-            Ok(canonical) => canonical,
-            Err(e) => return Ok(Err(e)),
-        };
-        let account = BankAccount::try_from(input.to_string())?;
-        if account != normalized {
-            Ok(Err(SimpleVMError::InvalidAddress))
-        } else {
-            Ok(Ok(()))
-        }
+        Ok(self
+            .extension
+            .address_mode
+            .validate(&BankAccount(input.into())))
     }
 
     fn addr_canonicalize(
         &mut self,
         input: &str,
     ) -> Result<Result<Self::CanonicalAddress, Self::Error>, Self::Error> {
-        // mimicks formats like hex or bech32 where different casings are valid for one address
-        let normalized = input.to_lowercase();
-
-        // Dummy input validation. This is more sophisticated for formats like bech32, where format and checksum are validated.
-        if normalized.len() < 3 {
-            return Ok(Err(SimpleVMError::InvalidAddress));
-        }
-
-        if normalized.len() > CANONICAL_LENGTH {
-            return Ok(Err(SimpleVMError::InvalidAddress));
-        }
-
-        let mut out = Vec::from(normalized);
-        // pad to canonical length with NULL bytes
-        out.resize(CANONICAL_LENGTH, 0x00);
-        // content-dependent rotate followed by shuffle to destroy
-        let rotate_by = digit_sum(&out) % CANONICAL_LENGTH;
-        out.rotate_left(rotate_by);
-        for _ in 0..SHUFFLES_ENCODE {
-            out = riffle_shuffle(&out);
-        }
-        Ok(Ok(out.try_into()?))
+        Ok(self
+            .extension
+            .address_mode
+            .canonicalize(input)
+            .and_then(CanonicalAddress::try_from))
     }
 
     fn addr_humanize(
         &mut self,
         addr: &Self::CanonicalAddress,
     ) -> Result<Result<Self::Address, Self::Error>, Self::Error> {
-        if addr.0.len() != CANONICAL_LENGTH {
-            return Ok(Err(SimpleVMError::InvalidAddress));
-        }
-
-        let mut tmp: Vec<u8> = addr.clone().into();
-        // Shuffle two more times which restored the original value (24 elements are back to original after 20 rounds)
-        for _ in 0..SHUFFLES_DECODE {
-            tmp = riffle_shuffle(&tmp);
-        }
-        // Rotate back
-        let rotate_by = digit_sum(&tmp) % CANONICAL_LENGTH;
-        tmp.rotate_right(rotate_by);
-        // Remove NULL bytes (i.e. the padding)
-        let trimmed = tmp.into_iter().filter(|&x| x != 0x00).collect();
-        // decode UTF-8 bytes into string
-        let human = match String::from_utf8(trimmed) {
-            Ok(trimmed) => trimmed,
-            Err(_) => return Ok(Err(SimpleVMError::InvalidAddress)),
-        };
-        Ok(
-            BankAccount::try_from(Addr::unchecked(human))
-                .map_err(|_| SimpleVMError::InvalidAddress),
-        )
+        let canonical: Vec<u8> = addr.clone().into();
+        Ok(self.extension.address_mode.humanize(&canonical))
     }
 
     fn db_read(
@@ -605,7 +1575,7 @@ impl<'a> VMBase for SimpleWasmiVM<'a> {
             .get(&contract_addr)
             .unwrap_or(&empty)
             .data
-            .get(&hex::encode(key))
+            .get(&key)
             .cloned())
     }
 
@@ -615,36 +1585,31 @@ impl<'a> VMBase for SimpleWasmiVM<'a> {
         value: Self::StorageValue,
     ) -> Result<(), Self::Error> {
         let contract_addr = self.env.contract.address.clone().try_into()?;
-        self.extension
-            .storage
-            .entry(contract_addr)
-            .or_insert_with(SimpleWasmiVMStorage::default)
-            .data
-            .insert(hex::encode(key), value);
+        self.extension.set_storage(contract_addr, key, Some(value));
         Ok(())
     }
 
     fn db_remove(&mut self, key: Self::StorageKey) -> Result<(), Self::Error> {
         let contract_addr = self.env.contract.address.clone().try_into()?;
-        self.extension
-            .storage
-            .get_mut(&contract_addr)
-            .map(|contract_storage| contract_storage.data.remove(&hex::encode(key)));
+        self.extension.set_storage(contract_addr, key, None);
         Ok(())
     }
 
     fn abort(&mut self, message: String) -> Result<(), Self::Error> {
+        // The abort is what matters, even if its debug message cannot be attributed.
+        let _ = self.emit_debug(DebugMessageKind::Abort, message.clone());
         Err(SimpleVMError::from(WasmiVMError::from(
             SystemError::ContractExecutionFailure(message),
         )))
     }
 
     fn charge(&mut self, value: VmGas) -> Result<(), Self::Error> {
+        let category = gas_category(&value);
         let gas_to_charge = match value {
             VmGas::Instrumentation { metered } => metered as u64,
             _ => 1u64,
         };
-        self.extension.gas.charge(gas_to_charge)?;
+        self.extension.charge(category, gas_to_charge)?;
         Ok(())
     }
 
@@ -673,19 +1638,20 @@ impl<'a> VMBase for SimpleWasmiVM<'a> {
     }
 }
 
-#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
-pub struct BankAccount(pub u32);
+/// An account of the chain, formatted according to the `AddressMode` of the chain.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct BankAccount(pub String);
 
 impl WasmDescribe for BankAccount {
     fn describe() {
-        <u32 as WasmDescribe>::describe()
+        <String as WasmDescribe>::describe()
     }
 }
 
 impl FromWasmAbi for BankAccount {
-    type Abi = <u32 as FromWasmAbi>::Abi;
+    type Abi = <String as FromWasmAbi>::Abi;
     unsafe fn from_abi(js: Self::Abi) -> Self {
-        BankAccount(u32::from_abi(js))
+        BankAccount(String::from_abi(js))
     }
 }
 
@@ -707,15 +1673,152 @@ impl TryFrom<Addr> for BankAccount {
 impl TryFrom<String> for BankAccount {
     type Error = SimpleVMError;
     fn try_from(value: String) -> Result<Self, Self::Error> {
-        Ok(BankAccount(
-            u32::from_str(&value).map_err(|_| SimpleVMError::InvalidAccountFormat)?,
-        ))
+        if value.is_empty() {
+            return Err(SimpleVMError::InvalidAccountFormat);
+        }
+        Ok(BankAccount(value))
     }
 }
 
 impl From<BankAccount> for Addr {
     fn from(BankAccount(account): BankAccount) -> Self {
-        Addr::unchecked(format!("{}", account))
+        Addr::unchecked(account)
+    }
+}
+
+/// How the accounts of the chain are formatted and canonicalized.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum AddressMode {
+    /// Decimal numbers, zero padded to three digits and canonicalized like the
+    /// CosmWasm mock api does.
+    #[default]
+    Numeric,
+    /// Bech32 strings with the `prefix` human readable part, canonicalized to
+    /// their 20 or 32 bytes payload.
+    Bech32 { prefix: String },
+}
+
+impl AddressMode {
+    /// Bech32 mode, `prefix` must be a valid lower case human readable part.
+    pub fn bech32(prefix: String) -> Result<Self, SimpleVMError> {
+        let valid = prefix == prefix.to_lowercase()
+            && bech32::encode(&prefix, Vec::<bech32::u5>::new(), bech32::Variant::Bech32).is_ok();
+        if !valid {
+            return Err(SimpleVMError::InvalidAddress);
+        }
+        Ok(AddressMode::Bech32 { prefix })
+    }
+
+    /// Address of the account named `name`, derived from the hash of the name.
+    pub fn named_account(&self, name: &str) -> Result<BankAccount, SimpleVMError> {
+        let hash = Sha256::digest(name.as_bytes());
+        match self {
+            AddressMode::Numeric => {
+                let mut id = [0u8; 8];
+                id.copy_from_slice(&hash[..8]);
+                Ok(numeric_account(u64::from_be_bytes(id)))
+            }
+            AddressMode::Bech32 { .. } => self.humanize(&hash[..BECH32_ACCOUNT_LENGTH]),
+        }
+    }
+
+    /// Address of the `instance_id`-th contract, following wasmd classic derivation.
+    /// Numeric accounts are simply the zero padded instance id.
+    pub fn contract_address(
+        &self,
+        code_id: CosmwasmCodeId,
+        instance_id: u64,
+    ) -> Result<BankAccount, SimpleVMError> {
+        match self {
+            AddressMode::Numeric => Ok(numeric_account(instance_id)),
+            AddressMode::Bech32 { .. } => {
+                let mut key = Vec::with_capacity(16);
+                key.extend_from_slice(&code_id.to_be_bytes());
+                key.extend_from_slice(&instance_id.to_be_bytes());
+                self.humanize(&wasm_module_address(&key))
+            }
+        }
+    }
+
+    /// Address of a contract instantiated with `instantiate2`, which only depends on
+    /// the checksum of its code, its creator and the salt.
+    /// It can't be represented by numeric accounts.
+    pub fn predictable_contract_address(
+        &self,
+        checksum: &[u8],
+        creator: &BankAccount,
+        salt: &[u8],
+    ) -> Result<BankAccount, SimpleVMError> {
+        match self {
+            AddressMode::Numeric => Err(SimpleVMError::Unsupported),
+            AddressMode::Bech32 { .. } => {
+                if salt.is_empty() || salt.len() > MAX_SALT_LENGTH {
+                    return Err(SimpleVMError::InvalidSalt);
+                }
+                let creator = self.canonicalize(&creator.0)?;
+                // Each part is length prefixed, the init message is not part of the key.
+                let mut key = Vec::new();
+                let parts: [&[u8]; 4] = [checksum, &creator, salt, &[]];
+                for part in parts {
+                    key.extend_from_slice(&(part.len() as u64).to_be_bytes());
+                    key.extend_from_slice(part);
+                }
+                self.humanize(&wasm_module_address(&key))
+            }
+        }
+    }
+
+    pub fn canonicalize(&self, input: &str) -> Result<Vec<u8>, SimpleVMError> {
+        match self {
+            AddressMode::Numeric => mock_canonicalize(input),
+            AddressMode::Bech32 { prefix } => {
+                let (hrp, data, variant) =
+                    bech32::decode(input).map_err(|_| SimpleVMError::InvalidAddress)?;
+                if hrp != *prefix || variant != bech32::Variant::Bech32 {
+                    return Err(SimpleVMError::InvalidAddress);
+                }
+                let payload =
+                    Vec::<u8>::from_base32(&data).map_err(|_| SimpleVMError::InvalidAddress)?;
+                if !matches!(
+                    payload.len(),
+                    BECH32_ACCOUNT_LENGTH | BECH32_CONTRACT_LENGTH
+                ) {
+                    return Err(SimpleVMError::InvalidAddress);
+                }
+                Ok(payload)
+            }
+        }
+    }
+
+    pub fn humanize(&self, canonical: &[u8]) -> Result<BankAccount, SimpleVMError> {
+        match self {
+            AddressMode::Numeric => {
+                let human = mock_humanize(canonical)?;
+                u64::from_str(&human).map_err(|_| SimpleVMError::InvalidAddress)?;
+                Ok(BankAccount(human))
+            }
+            AddressMode::Bech32 { prefix } => {
+                if !matches!(
+                    canonical.len(),
+                    BECH32_ACCOUNT_LENGTH | BECH32_CONTRACT_LENGTH
+                ) {
+                    return Err(SimpleVMError::InvalidAddress);
+                }
+                bech32::encode(prefix, canonical.to_base32(), bech32::Variant::Bech32)
+                    .map(BankAccount)
+                    .map_err(|_| SimpleVMError::InvalidAddress)
+            }
+        }
+    }
+
+    /// Ensure `account` is an address of this mode, in its normalized form.
+    pub fn validate(&self, account: &BankAccount) -> Result<(), SimpleVMError> {
+        let canonical = self.canonicalize(&account.0)?;
+        if self.humanize(&canonical)? != *account {
+            return Err(SimpleVMError::InvalidAddress);
+        }
+        Ok(())
     }
 }
 
@@ -733,33 +1836,419 @@ impl<'a> Has<MessageInfo> for SimpleWasmiVM<'a> {
 impl<'a> Transactional for SimpleWasmiVM<'a> {
     type Error = SimpleVMError;
     fn transaction_begin(&mut self) -> Result<(), Self::Error> {
-        self.extension.transaction_depth += 1;
+        self.extension.transaction_begin();
         Ok(())
     }
     fn transaction_commit(&mut self) -> Result<(), Self::Error> {
-        self.extension.transaction_depth -= 1;
+        self.extension.transaction_commit();
         Ok(())
     }
     fn transaction_rollback(&mut self) -> Result<(), Self::Error> {
-        self.extension.transaction_depth -= 1;
+        self.extension.transaction_rollback();
         Ok(())
     }
 }
 
-struct ConstantCostRules;
-impl Rules for ConstantCostRules {
-    fn instruction_cost(
-        &self,
-        _: &wasm_instrument::parity_wasm::elements::Instruction,
-    ) -> Option<u32> {
-        Some(42)
+/// Input of the `sudo` export, a privileged call made by the chain itself,
+/// without sender nor funds. It shares the result of `migrate`.
+pub struct SudoInput<T = Empty>(PhantomData<T>);
+impl<T> Input for SudoInput<T> {
+    type Output = <MigrateInput<T> as Input>::Output;
+}
+impl<T> AsFunctionName for SudoInput<T> {
+    const NAME: &'static str = "sudo";
+}
+impl<T> HasInfo for SudoInput<T> {
+    const HAS_INFO: bool = false;
+}
+impl<T> HasEvent for SudoInput<T> {
+    fn generate_event(contract_address: String) -> Event {
+        Event::new("sudo").add_attribute("_contract_address", contract_address)
+    }
+}
+
+/// Gas metering rules applied to the uploaded code before it is executed.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct GasRules {
+    /// Cost of any instruction without a specific cost.
+    pub instruction_cost: u32,
+    /// Specific costs, keyed by the parity-wasm name of an MVP instruction, e.g. `Call`,
+    /// `GetLocal` or `I64Mul`, see `instruction_name`. Other keys are ignored.
+    #[serde(default)]
+    pub instruction_costs: BTreeMap<String, u32>,
+    /// Cost per page of grown memory, `0` makes growing memory free.
+    pub memory_grow_cost: u32,
+    /// Maximum stack height a contract can reach before trapping.
+    pub stack_limit: u32,
+}
+
+impl Default for GasRules {
+    fn default() -> Self {
+        GasRules {
+            instruction_cost: 42,
+            instruction_costs: Default::default(),
+            memory_grow_cost: 1024,
+            stack_limit: 64 * 1024,
+        }
     }
+}
 
-    fn memory_grow_cost(&self) -> wasm_instrument::gas_metering::MemoryGrowCost {
-        wasm_instrument::gas_metering::MemoryGrowCost::Linear(
-            NonZeroU32::new(1024).expect("impossible"),
+impl Rules for GasRules {
+    fn instruction_cost(&self, instruction: &Instruction) -> Option<u32> {
+        Some(
+            instruction_name(instruction)
+                .and_then(|name| self.instruction_costs.get(name))
+                .copied()
+                .unwrap_or(self.instruction_cost),
         )
     }
+
+    fn memory_grow_cost(&self) -> MemoryGrowCost {
+        match NonZeroU32::new(self.memory_grow_cost) {
+            Some(cost) => MemoryGrowCost::Linear(cost),
+            None => MemoryGrowCost::Free,
+        }
+    }
+}
+
+/// Name under which the cost of `instruction` can be configured in
+/// `GasRules::instruction_costs`: the name of the MVP instruction in parity-wasm,
+/// e.g. `Call`, `GetLocal` or `I64Mul`. Instructions of wasm proposals have none.
+fn instruction_name(instruction: &Instruction) -> Option<&'static str> {
+    let name = match instruction {
+        Instruction::Unreachable { .. } => "Unreachable",
+        Instruction::Nop { .. } => "Nop",
+        Instruction::Block { .. } => "Block",
+        Instruction::Loop { .. } => "Loop",
+        Instruction::If { .. } => "If",
+        Instruction::Else { .. } => "Else",
+        Instruction::End { .. } => "End",
+        Instruction::Br { .. } => "Br",
+        Instruction::BrIf { .. } => "BrIf",
+        Instruction::BrTable { .. } => "BrTable",
+        Instruction::Return { .. } => "Return",
+        Instruction::Call { .. } => "Call",
+        Instruction::CallIndirect { .. } => "CallIndirect",
+        Instruction::Drop { .. } => "Drop",
+        Instruction::Select { .. } => "Select",
+        Instruction::GetLocal { .. } => "GetLocal",
+        Instruction::SetLocal { .. } => "SetLocal",
+        Instruction::TeeLocal { .. } => "TeeLocal",
+        Instruction::GetGlobal { .. } => "GetGlobal",
+        Instruction::SetGlobal { .. } => "SetGlobal",
+        Instruction::I32Load { .. } => "I32Load",
+        Instruction::I64Load { .. } => "I64Load",
+        Instruction::F32Load { .. } => "F32Load",
+        Instruction::F64Load { .. } => "F64Load",
+        Instruction::I32Load8S { .. } => "I32Load8S",
+        Instruction::I32Load8U { .. } => "I32Load8U",
+        Instruction::I32Load16S { .. } => "I32Load16S",
+        Instruction::I32Load16U { .. } => "I32Load16U",
+        Instruction::I64Load8S { .. } => "I64Load8S",
+        Instruction::I64Load8U { .. } => "I64Load8U",
+        Instruction::I64Load16S { .. } => "I64Load16S",
+        Instruction::I64Load16U { .. } => "I64Load16U",
+        Instruction::I64Load32S { .. } => "I64Load32S",
+        Instruction::I64Load32U { .. } => "I64Load32U",
+        Instruction::I32Store { .. } => "I32Store",
+        Instruction::I64Store { .. } => "I64Store",
+        Instruction::F32Store { .. } => "F32Store",
+        Instruction::F64Store { .. } => "F64Store",
+        Instruction::I32Store8 { .. } => "I32Store8",
+        Instruction::I32Store16 { .. } => "I32Store16",
+        Instruction::I64Store8 { .. } => "I64Store8",
+        Instruction::I64Store16 { .. } => "I64Store16",
+        Instruction::I64Store32 { .. } => "I64Store32",
+        Instruction::CurrentMemory { .. } => "CurrentMemory",
+        Instruction::GrowMemory { .. } => "GrowMemory",
+        Instruction::I32Const { .. } => "I32Const",
+        Instruction::I64Const { .. } => "I64Const",
+        Instruction::F32Const { .. } => "F32Const",
+        Instruction::F64Const { .. } => "F64Const",
+        Instruction::I32Eqz { .. } => "I32Eqz",
+        Instruction::I32Eq { .. } => "I32Eq",
+        Instruction::I32Ne { .. } => "I32Ne",
+        Instruction::I32LtS { .. } => "I32LtS",
+        Instruction::I32LtU { .. } => "I32LtU",
+        Instruction::I32GtS { .. } => "I32GtS",
+        Instruction::I32GtU { .. } => "I32GtU",
+        Instruction::I32LeS { .. } => "I32LeS",
+        Instruction::I32LeU { .. } => "I32LeU",
+        Instruction::I32GeS { .. } => "I32GeS",
+        Instruction::I32GeU { .. } => "I32GeU",
+        Instruction::I64Eqz { .. } => "I64Eqz",
+        Instruction::I64Eq { .. } => "I64Eq",
+        Instruction::I64Ne { .. } => "I64Ne",
+        Instruction::I64LtS { .. } => "I64LtS",
+        Instruction::I64LtU { .. } => "I64LtU",
+        Instruction::I64GtS { .. } => "I64GtS",
+        Instruction::I64GtU { .. } => "I64GtU",
+        Instruction::I64LeS { .. } => "I64LeS",
+        Instruction::I64LeU { .. } => "I64LeU",
+        Instruction::I64GeS { .. } => "I64GeS",
+        Instruction::I64GeU { .. } => "I64GeU",
+        Instruction::F32Eq { .. } => "F32Eq",
+        Instruction::F32Ne { .. } => "F32Ne",
+        Instruction::F32Lt { .. } => "F32Lt",
+        Instruction::F32Gt { .. } => "F32Gt",
+        Instruction::F32Le { .. } => "F32Le",
+        Instruction::F32Ge { .. } => "F32Ge",
+        Instruction::F64Eq { .. } => "F64Eq",
+        Instruction::F64Ne { .. } => "F64Ne",
+        Instruction::F64Lt { .. } => "F64Lt",
+        Instruction::F64Gt { .. } => "F64Gt",
+        Instruction::F64Le { .. } => "F64Le",
+        Instruction::F64Ge { .. } => "F64Ge",
+        Instruction::I32Clz { .. } => "I32Clz",
+        Instruction::I32Ctz { .. } => "I32Ctz",
+        Instruction::I32Popcnt { .. } => "I32Popcnt",
+        Instruction::I32Add { .. } => "I32Add",
+        Instruction::I32Sub { .. } => "I32Sub",
+        Instruction::I32Mul { .. } => "I32Mul",
+        Instruction::I32DivS { .. } => "I32DivS",
+        Instruction::I32DivU { .. } => "I32DivU",
+        Instruction::I32RemS { .. } => "I32RemS",
+        Instruction::I32RemU { .. } => "I32RemU",
+        Instruction::I32And { .. } => "I32And",
+        Instruction::I32Or { .. } => "I32Or",
+        Instruction::I32Xor { .. } => "I32Xor",
+        Instruction::I32Shl { .. } => "I32Shl",
+        Instruction::I32ShrS { .. } => "I32ShrS",
+        Instruction::I32ShrU { .. } => "I32ShrU",
+        Instruction::I32Rotl { .. } => "I32Rotl",
+        Instruction::I32Rotr { .. } => "I32Rotr",
+        Instruction::I64Clz { .. } => "I64Clz",
+        Instruction::I64Ctz { .. } => "I64Ctz",
+        Instruction::I64Popcnt { .. } => "I64Popcnt",
+        Instruction::I64Add { .. } => "I64Add",
+        Instruction::I64Sub { .. } => "I64Sub",
+        Instruction::I64Mul { .. } => "I64Mul",
+        Instruction::I64DivS { .. } => "I64DivS",
+        Instruction::I64DivU { .. } => "I64DivU",
+        Instruction::I64RemS { .. } => "I64RemS",
+        Instruction::I64RemU { .. } => "I64RemU",
+        Instruction::I64And { .. } => "I64And",
+        Instruction::I64Or { .. } => "I64Or",
+        Instruction::I64Xor { .. } => "I64Xor",
+        Instruction::I64Shl { .. } => "I64Shl",
+        Instruction::I64ShrS { .. } => "I64ShrS",
+        Instruction::I64ShrU { .. } => "I64ShrU",
+        Instruction::I64Rotl { .. } => "I64Rotl",
+        Instruction::I64Rotr { .. } => "I64Rotr",
+        Instruction::F32Abs { .. } => "F32Abs",
+        Instruction::F32Neg { .. } => "F32Neg",
+        Instruction::F32Ceil { .. } => "F32Ceil",
+        Instruction::F32Floor { .. } => "F32Floor",
+        Instruction::F32Trunc { .. } => "F32Trunc",
+        Instruction::F32Nearest { .. } => "F32Nearest",
+        Instruction::F32Sqrt { .. } => "F32Sqrt",
+        Instruction::F32Add { .. } => "F32Add",
+        Instruction::F32Sub { .. } => "F32Sub",
+        Instruction::F32Mul { .. } => "F32Mul",
+        Instruction::F32Div { .. } => "F32Div",
+        Instruction::F32Min { .. } => "F32Min",
+        Instruction::F32Max { .. } => "F32Max",
+        Instruction::F32Copysign { .. } => "F32Copysign",
+        Instruction::F64Abs { .. } => "F64Abs",
+        Instruction::F64Neg { .. } => "F64Neg",
+        Instruction::F64Ceil { .. } => "F64Ceil",
+        Instruction::F64Floor { .. } => "F64Floor",
+        Instruction::F64Trunc { .. } => "F64Trunc",
+        Instruction::F64Nearest { .. } => "F64Nearest",
+        Instruction::F64Sqrt { .. } => "F64Sqrt",
+        Instruction::F64Add { .. } => "F64Add",
+        Instruction::F64Sub { .. } => "F64Sub",
+        Instruction::F64Mul { .. } => "F64Mul",
+        Instruction::F64Div { .. } => "F64Div",
+        Instruction::F64Min { .. } => "F64Min",
+        Instruction::F64Max { .. } => "F64Max",
+        Instruction::F64Copysign { .. } => "F64Copysign",
+        Instruction::I32WrapI64 { .. } => "I32WrapI64",
+        Instruction::I32TruncSF32 { .. } => "I32TruncSF32",
+        Instruction::I32TruncUF32 { .. } => "I32TruncUF32",
+        Instruction::I32TruncSF64 { .. } => "I32TruncSF64",
+        Instruction::I32TruncUF64 { .. } => "I32TruncUF64",
+        Instruction::I64ExtendSI32 { .. } => "I64ExtendSI32",
+        Instruction::I64ExtendUI32 { .. } => "I64ExtendUI32",
+        Instruction::I64TruncSF32 { .. } => "I64TruncSF32",
+        Instruction::I64TruncUF32 { .. } => "I64TruncUF32",
+        Instruction::I64TruncSF64 { .. } => "I64TruncSF64",
+        Instruction::I64TruncUF64 { .. } => "I64TruncUF64",
+        Instruction::F32ConvertSI32 { .. } => "F32ConvertSI32",
+        Instruction::F32ConvertUI32 { .. } => "F32ConvertUI32",
+        Instruction::F32ConvertSI64 { .. } => "F32ConvertSI64",
+        Instruction::F32ConvertUI64 { .. } => "F32ConvertUI64",
+        Instruction::F32DemoteF64 { .. } => "F32DemoteF64",
+        Instruction::F64ConvertSI32 { .. } => "F64ConvertSI32",
+        Instruction::F64ConvertUI32 { .. } => "F64ConvertUI32",
+        Instruction::F64ConvertSI64 { .. } => "F64ConvertSI64",
+        Instruction::F64ConvertUI64 { .. } => "F64ConvertUI64",
+        Instruction::F64PromoteF32 { .. } => "F64PromoteF32",
+        Instruction::I32ReinterpretF32 { .. } => "I32ReinterpretF32",
+        Instruction::I64ReinterpretF64 { .. } => "I64ReinterpretF64",
+        Instruction::F32ReinterpretI32 { .. } => "F32ReinterpretI32",
+        Instruction::F64ReinterpretI64 { .. } => "F64ReinterpretI64",
+        #[cfg(feature = "sign_ext")]
+        Instruction::SignExt(_) => return None,
+    };
+    Some(name)
+}
+
+/// Ensure `code` is a module the VM is able to run: it must export the functions
+/// required by the CosmWasm interface and only import known host functions.
+pub fn validate(code: &[u8]) -> Result<(), SimpleVMError> {
+    let module: Module = wasm_instrument::parity_wasm::deserialize_buffer(code)
+        .map_err(|_| SimpleVMError::InvalidCode)?;
+    let exports = module
+        .export_section()
+        .map(|section| section.entries())
+        .unwrap_or_default();
+    for required in REQUIRED_EXPORTS {
+        if !exports.iter().any(|export| export.field() == required) {
+            return Err(SimpleVMError::MissingExport(required.into()));
+        }
+    }
+    let host_functions_definitions = host_functions::definitions::<SimpleWasmiVM>();
+    let imports = module
+        .import_section()
+        .map(|section| section.entries())
+        .unwrap_or_default();
+    for import in imports {
+        let known = host_functions_definitions
+            .get(import.module())
+            .map_or(false, |functions| functions.contains_key(import.field()));
+        if !known {
+            return Err(SimpleVMError::UnknownImport(format!(
+                "{}.{}",
+                import.module(),
+                import.field()
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Inject gas metering, charged through the `env.gas` host function, and stack
+/// height limiting into `code`.
+pub fn instrument(code: &[u8], rules: &GasRules) -> Result<Vec<u8>, SimpleVMError> {
+    let module: Module = wasm_instrument::parity_wasm::deserialize_buffer(code)
+        .map_err(|_| SimpleVMError::InstrumentationFailed)?;
+    let module = wasm_instrument::gas_metering::inject(module, rules, "env")
+        .map_err(|_| SimpleVMError::InstrumentationFailed)?;
+    let module = wasm_instrument::inject_stack_limiter(module, rules.stack_limit)
+        .map_err(|_| SimpleVMError::InstrumentationFailed)?;
+    wasm_instrument::parity_wasm::serialize(module)
+        .map_err(|_| SimpleVMError::InstrumentationFailed)
+}
+
+/// Breakdown category of a charge, the snake cased name of the `VmGas` variant,
+/// e.g. `db_read` or `instrumentation`.
+fn gas_category(value: &VmGas) -> &'static str {
+    match value {
+        VmGas::Instrumentation { .. } => "instrumentation",
+        VmGas::RawCall => "raw_call",
+        VmGas::SetContractMeta => "set_contract_meta",
+        VmGas::GetContractMeta => "get_contract_meta",
+        VmGas::QueryContinuation => "query_continuation",
+        VmGas::ContinueExecute { .. } => "continue_execute",
+        VmGas::ContinueInstantiate { .. } => "continue_instantiate",
+        VmGas::ContinueMigrate => "continue_migrate",
+        VmGas::QueryCustom => "query_custom",
+        VmGas::MessageCustom => "message_custom",
+        VmGas::QueryRaw => "query_raw",
+        VmGas::Transfer { .. } => "transfer",
+        VmGas::Burn => "burn",
+        VmGas::Balance => "balance",
+        VmGas::AllBalance => "all_balance",
+        VmGas::QueryInfo => "query_info",
+        VmGas::QueryChain => "query_chain",
+        VmGas::DbRead => "db_read",
+        VmGas::DbWrite => "db_write",
+        VmGas::DbRemove => "db_remove",
+        VmGas::DbScan => "db_scan",
+        VmGas::DbNext => "db_next",
+        VmGas::Debug => "debug",
+        VmGas::Secp256k1Verify => "secp256k1_verify",
+        VmGas::Secp256k1RecoverPubkey => "secp256k1_recover_pubkey",
+        VmGas::Ed25519Verify => "ed25519_verify",
+        VmGas::Ed25519BatchVerify => "ed25519_batch_verify",
+        VmGas::AddrValidate => "addr_validate",
+        VmGas::AddrCanonicalize => "addr_canonicalize",
+        VmGas::AddrHumanize => "addr_humanize",
+    }
+}
+
+/// Address of the account derived from `key` by the wasm module, see `address.Module`
+/// of the cosmos sdk.
+fn wasm_module_address(key: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update(Sha256::digest(b"module"));
+    hasher.update(b"wasm\0");
+    hasher.update(key);
+    hasher.finalize().to_vec()
+}
+
+/// Event wasmd emits when the admin of the contract at `address` is updated or cleared.
+pub fn update_admin_event(address: &BankAccount, admin: Option<&BankAccount>) -> Event {
+    Event::new("update_contract_admin")
+        .add_attribute("_contract_address", address.0.clone())
+        .add_attribute(
+            "new_admin_address",
+            admin
+                .map(|BankAccount(admin)| admin.clone())
+                .unwrap_or_default(),
+        )
+}
+
+/// Numeric account of `id`, zero padded so that the mock canonicalization accepts it.
+fn numeric_account(id: u64) -> BankAccount {
+    BankAccount(format!("{:0width$}", id, width = MOCK_MIN_LENGTH))
+}
+
+/// Canonicalize `input` the way the CosmWasm mock api does, a reversible shuffle
+/// of the padded, lower cased, input.
+fn mock_canonicalize(input: &str) -> Result<Vec<u8>, SimpleVMError> {
+    // mimicks formats like hex or bech32 where different casings are valid for one address
+    let normalized = input.to_lowercase();
+
+    // Dummy input validation. This is more sophisticated for formats like bech32, where format and checksum are validated.
+    if normalized.len() < MOCK_MIN_LENGTH {
+        return Err(SimpleVMError::InvalidAddress);
+    }
+
+    if normalized.len() > CANONICAL_LENGTH {
+        return Err(SimpleVMError::InvalidAddress);
+    }
+
+    let mut out = Vec::from(normalized);
+    // pad to canonical length with NULL bytes
+    out.resize(CANONICAL_LENGTH, 0x00);
+    // content-dependent rotate followed by shuffle to destroy
+    let rotate_by = digit_sum(&out) % CANONICAL_LENGTH;
+    out.rotate_left(rotate_by);
+    for _ in 0..SHUFFLES_ENCODE {
+        out = riffle_shuffle(&out);
+    }
+    Ok(out)
+}
+
+fn mock_humanize(canonical: &[u8]) -> Result<String, SimpleVMError> {
+    if canonical.len() != CANONICAL_LENGTH {
+        return Err(SimpleVMError::InvalidAddress);
+    }
+
+    let mut tmp = canonical.to_vec();
+    // Shuffle two more times which restored the original value (24 elements are back to original after 20 rounds)
+    for _ in 0..SHUFFLES_DECODE {
+        tmp = riffle_shuffle(&tmp);
+    }
+    // Rotate back
+    let rotate_by = digit_sum(&tmp) % CANONICAL_LENGTH;
+    tmp.rotate_right(rotate_by);
+    // Remove NULL bytes (i.e. the padding)
+    let trimmed = tmp.into_iter().filter(|&x| x != 0x00).collect();
+    // decode UTF-8 bytes into string
+    String::from_utf8(trimmed).map_err(|_| SimpleVMError::InvalidAddress)
 }
 
 pub fn digit_sum(input: &[u8]) -> usize {
@@ -780,3 +2269,1152 @@ pub fn riffle_shuffle<T: Clone>(input: &[T]) -> Vec<T> {
     }
     out
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn extension() -> SimpleWasmiVMExtension {
+        SimpleWasmiVMExtension::new(DEFAULT_GAS_LIMIT, AddressMode::Numeric)
+    }
+
+    fn coin(amount: u128, denom: &str) -> Coin {
+        Coin {
+            denom: denom.into(),
+            amount: amount.into(),
+        }
+    }
+
+    fn meta(code_id: CosmwasmCodeId) -> CosmwasmContractMeta<BankAccount> {
+        CosmwasmContractMeta {
+            code_id,
+            admin: None,
+            label: String::new(),
+        }
+    }
+
+    #[test]
+    fn rollback_restores_the_state_the_transaction_began_with() {
+        let mut extension = extension();
+        extension.set_code(1, Some(SimpleWasmiVMCode::new(vec![0])));
+        extension.set_storage(numeric_account(1), b"a".to_vec(), Some(vec![1]));
+        let before = extension.clone();
+
+        extension.transaction_begin();
+        extension.set_storage(numeric_account(1), b"a".to_vec(), Some(vec![2]));
+        extension.set_storage(numeric_account(1), b"b".to_vec(), Some(vec![3]));
+        let address = numeric_account(extension.next_instance_id());
+        extension.set_contract(address, Some(meta(1)));
+        extension.set_code(1, None);
+        extension.transaction_rollback();
+
+        assert_eq!(extension, before);
+        assert_eq!(extension.transaction_depth, 0);
+    }
+
+    #[test]
+    fn nested_rollback_only_reverts_the_inner_transaction() {
+        let mut extension = extension();
+
+        extension.transaction_begin();
+        extension.set_storage(numeric_account(1), b"a".to_vec(), Some(vec![1]));
+        let after_outer = extension.clone();
+
+        extension.transaction_begin();
+        extension.set_storage(numeric_account(1), b"a".to_vec(), None);
+        extension.next_instance_id();
+        extension.transaction_rollback();
+        assert_eq!(extension, after_outer);
+
+        extension.transaction_commit();
+        assert_eq!(extension.journal, Journal::default());
+        assert_eq!(
+            extension.storage[&numeric_account(1)]
+                .data
+                .get(b"a".as_slice()),
+            Some(&vec![1])
+        );
+    }
+
+    #[test]
+    fn committed_inner_transaction_is_reverted_by_outer_rollback() {
+        let mut extension = extension();
+        let before = extension.clone();
+
+        extension.transaction_begin();
+        extension.transaction_begin();
+        let address = numeric_account(extension.next_instance_id());
+        extension.set_contract(address, Some(meta(1)));
+        extension.transaction_commit();
+        extension.transaction_rollback();
+
+        assert_eq!(extension, before);
+    }
+
+    #[test]
+    fn transfer_moves_funds_between_accounts() {
+        let mut extension = extension();
+        extension
+            .mint(numeric_account(1), &[coin(100, "uatom"), coin(5, "ujuno")])
+            .unwrap();
+        extension
+            .transfer(
+                numeric_account(1),
+                numeric_account(2),
+                &[coin(40, "uatom"), coin(5, "ujuno")],
+            )
+            .unwrap();
+
+        assert_eq!(extension.balance(&numeric_account(1), "uatom"), 60);
+        assert_eq!(
+            extension.all_balance(&numeric_account(1)),
+            vec![coin(60, "uatom")]
+        );
+        assert_eq!(
+            extension.all_balance(&numeric_account(2)),
+            vec![coin(40, "uatom"), coin(5, "ujuno")]
+        );
+    }
+
+    #[test]
+    fn insufficient_funds_leave_balances_untouched() {
+        let mut extension = extension();
+        extension
+            .mint(numeric_account(1), &[coin(100, "uatom")])
+            .unwrap();
+        let before = extension.clone();
+
+        let result = extension.transfer(
+            numeric_account(1),
+            numeric_account(2),
+            &[coin(50, "uatom"), coin(51, "uatom")],
+        );
+
+        assert!(matches!(result, Err(SimpleVMError::InsufficientFunds)));
+        assert_eq!(extension, before);
+    }
+
+    #[test]
+    fn minting_past_the_maximum_balance_overflows() {
+        let mut extension = extension();
+        extension
+            .mint(numeric_account(1), &[coin(u128::MAX, "uatom")])
+            .unwrap();
+        let before = extension.clone();
+
+        let result = extension.mint(numeric_account(1), &[coin(1, "uatom")]);
+
+        assert!(matches!(result, Err(SimpleVMError::BalanceOverflow)));
+        assert_eq!(extension, before);
+    }
+
+    #[test]
+    fn zero_amounts_are_skipped() {
+        let mut extension = extension();
+        extension
+            .transfer(numeric_account(1), numeric_account(2), &[coin(0, "uatom")])
+            .unwrap();
+
+        assert!(extension.balances.is_empty());
+    }
+
+    #[test]
+    fn balances_are_serialized_as_decimal_strings() {
+        let mut extension = extension();
+        extension
+            .mint(numeric_account(1), &[coin(u128::MAX, "uatom")])
+            .unwrap();
+
+        let state = serde_json::to_string(&extension).unwrap();
+
+        assert!(state
+            .contains(r#""balances":{"001":{"uatom":"340282366920938463463374607431768211455"}}"#));
+        assert_eq!(
+            serde_json::from_str::<SimpleWasmiVMExtension>(&state).unwrap(),
+            extension
+        );
+    }
+
+    fn storage(keys: &[&[u8]]) -> SimpleWasmiVMStorage {
+        SimpleWasmiVMStorage {
+            data: keys
+                .iter()
+                .map(|key| (key.to_vec(), key.to_vec()))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn keys(entries: Vec<(Vec<u8>, Vec<u8>)>) -> Vec<Vec<u8>> {
+        entries.into_iter().map(|(key, _)| key).collect()
+    }
+
+    #[test]
+    fn range_includes_start_and_excludes_end() {
+        let storage = storage(&[b"a", b"b", b"c", b"d"]);
+
+        assert_eq!(
+            keys(storage.range(Some(b"b".to_vec()), Some(b"d".to_vec()), Order::Ascending)),
+            vec![b"b".to_vec(), b"c".to_vec()]
+        );
+        assert_eq!(
+            keys(storage.range(Some(b"b".to_vec()), Some(b"d".to_vec()), Order::Descending)),
+            vec![b"c".to_vec(), b"b".to_vec()]
+        );
+        assert_eq!(
+            keys(storage.range(None, Some(b"b".to_vec()), Order::Ascending)),
+            vec![b"a".to_vec()]
+        );
+        assert_eq!(
+            keys(storage.range(Some(b"c".to_vec()), None, Order::Descending)),
+            vec![b"d".to_vec(), b"c".to_vec()]
+        );
+    }
+
+    #[test]
+    fn range_follows_the_byte_order_of_keys() {
+        // Prefixes sort before their extensions, bytes are compared as unsigned.
+        let storage = storage(&[&[0x01, 0x00], &[0x01], &[0xff], &[0x0a]]);
+
+        assert_eq!(
+            keys(storage.range(None, None, Order::Ascending)),
+            vec![vec![0x01], vec![0x01, 0x00], vec![0x0a], vec![0xff]]
+        );
+    }
+
+    #[test]
+    fn inverted_or_empty_ranges_are_empty() {
+        let storage = storage(&[b"a", b"b"]);
+
+        assert!(storage
+            .range(Some(b"b".to_vec()), Some(b"a".to_vec()), Order::Ascending)
+            .is_empty());
+        assert!(storage
+            .range(Some(b"a".to_vec()), Some(b"a".to_vec()), Order::Descending)
+            .is_empty());
+    }
+
+    #[test]
+    fn iterators_are_walked_to_an_empty_entry() {
+        let mut iterators = SimpleWasmiVMIterators::default();
+        let id = iterators.open(vec![(b"a".to_vec(), b"1".to_vec())]);
+
+        assert_eq!(iterators.next(id).unwrap(), (b"a".to_vec(), b"1".to_vec()));
+        assert_eq!(iterators.next(id).unwrap(), (Vec::new(), Vec::new()));
+        assert!(matches!(
+            iterators.next(id + 1),
+            Err(SimpleVMError::IteratorDoesNotExist)
+        ));
+    }
+
+    #[test]
+    fn iterator_ids_start_at_one_and_increase() {
+        let mut iterators = SimpleWasmiVMIterators::default();
+        let first = iterators.open(Vec::new());
+        let second = iterators.open(Vec::new());
+
+        assert_eq!((first, second), (1, 2));
+    }
+
+    const CW20_BASE: &[u8] = include_bytes!("../cw20_base.wasm");
+    const COSMWASMCEPTION: &[u8] = include_bytes!("../cosmwasmception.wasm");
+
+    fn code(code: &[u8]) -> SimpleWasmiVMCode {
+        SimpleWasmiVMCode::new(code.to_vec())
+    }
+
+    #[test]
+    fn contract_module_follows_the_code_id_of_the_contract() {
+        let mut extension = extension();
+        extension.set_code(1, Some(code(CW20_BASE)));
+        extension.set_contract(numeric_account(1), Some(meta(1)));
+        extension.set_contract(numeric_account(2), Some(meta(2)));
+
+        assert!(extension.contract_module(numeric_account(1)).is_ok());
+        assert!(matches!(
+            extension.contract_module(numeric_account(2)),
+            Err(SimpleVMError::CodeNotFound(2))
+        ));
+        assert!(matches!(
+            extension.contract_module(numeric_account(3)),
+            Err(SimpleVMError::ContractNotFound(address)) if address == numeric_account(3)
+        ));
+    }
+
+    #[test]
+    fn modules_are_compiled_once_per_code() {
+        let mut modules = ModuleCache::default();
+        let rules = GasRules::default();
+        let module = modules.get_or_compile(1, &code(CW20_BASE), &rules).unwrap();
+
+        assert!(Rc::ptr_eq(
+            &module,
+            &modules.get_or_compile(1, &code(CW20_BASE), &rules).unwrap()
+        ));
+        assert!(!Rc::ptr_eq(
+            &module,
+            &modules.get_or_compile(2, &code(CW20_BASE), &rules).unwrap()
+        ));
+    }
+
+    #[test]
+    fn replaced_code_is_recompiled() {
+        let mut modules = ModuleCache::default();
+        let rules = GasRules::default();
+        let module = modules.get_or_compile(1, &code(CW20_BASE), &rules).unwrap();
+        modules
+            .get_or_compile(1, &code(COSMWASMCEPTION), &rules)
+            .unwrap();
+
+        assert!(modules.get_or_compile(1, &code(&[0]), &rules).is_err());
+        assert!(!Rc::ptr_eq(
+            &module,
+            &modules.get_or_compile(1, &code(CW20_BASE), &rules).unwrap()
+        ));
+    }
+
+    #[test]
+    fn instructions_cost_their_specific_cost_or_the_default() {
+        let rules = GasRules {
+            instruction_costs: [("I64Mul".to_string(), 7), ("Call".to_string(), 9)]
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        };
+
+        assert_eq!(rules.instruction_cost(&Instruction::I64Mul), Some(7));
+        assert_eq!(rules.instruction_cost(&Instruction::Call(3)), Some(9));
+        assert_eq!(rules.instruction_cost(&Instruction::I64Add), Some(42));
+    }
+
+    #[test]
+    fn zero_memory_grow_cost_is_free() {
+        let rules = GasRules {
+            memory_grow_cost: 0,
+            ..Default::default()
+        };
+
+        assert!(matches!(rules.memory_grow_cost(), MemoryGrowCost::Free));
+        assert!(matches!(
+            GasRules::default().memory_grow_cost(),
+            MemoryGrowCost::Linear(cost) if cost.get() == 1024
+        ));
+    }
+
+    #[test]
+    fn instrumented_code_charges_gas_through_the_host() {
+        let code = instrument(CW20_BASE, &GasRules::default()).unwrap();
+        let module: Module = wasm_instrument::parity_wasm::deserialize_buffer(&code).unwrap();
+
+        assert!(module
+            .import_section()
+            .unwrap()
+            .entries()
+            .iter()
+            .any(|import| import.module() == "env" && import.field() == "gas"));
+        assert!(matches!(
+            instrument(&[0], &GasRules::default()),
+            Err(SimpleVMError::InstrumentationFailed)
+        ));
+    }
+
+    #[test]
+    fn changing_the_gas_rules_recompiles_modules() {
+        let mut extension = extension();
+        extension.set_code(1, Some(code(CW20_BASE)));
+        extension.set_contract(numeric_account(1), Some(meta(1)));
+        let module = extension.contract_module(numeric_account(1)).unwrap();
+
+        extension.set_gas_rules(GasRules {
+            instruction_cost: 1,
+            ..Default::default()
+        });
+
+        assert!(!Rc::ptr_eq(
+            &module,
+            &extension.contract_module(numeric_account(1)).unwrap()
+        ));
+    }
+
+    #[test]
+    fn charges_are_accounted_per_category() {
+        let mut extension = SimpleWasmiVMExtension::new(100, AddressMode::Numeric);
+        extension.charge("db_read", 10).unwrap();
+        extension.charge("db_write", 20).unwrap();
+        extension.charge("db_read", 5).unwrap();
+
+        assert_eq!(extension.gas.remaining(), 65);
+        assert_eq!(
+            extension.gas_breakdown,
+            [("db_read".to_string(), 15), ("db_write".to_string(), 20)]
+                .into_iter()
+                .collect()
+        );
+        assert!(matches!(
+            extension.charge("db_read", 66),
+            Err(SimpleVMError::OutOfGas)
+        ));
+
+        extension.gas_reset();
+
+        assert_eq!(extension.gas.remaining(), 100);
+        assert!(extension.gas_breakdown.is_empty());
+    }
+
+    #[test]
+    fn remaining_gas_includes_unpopped_checkpoints() {
+        let mut gas = Gas::new(100);
+        gas.push(VmGasCheckpoint::Limited(30)).unwrap();
+        gas.charge(10).unwrap();
+
+        assert_eq!(*gas.current(), 20);
+        assert_eq!(gas.remaining(), 90);
+        assert!(matches!(
+            gas.push(VmGasCheckpoint::Limited(21)),
+            Err(SimpleVMError::OutOfGas)
+        ));
+    }
+
+    #[test]
+    fn gas_categories_are_snake_cased_variant_names() {
+        assert_eq!(gas_category(&VmGas::DbRead), "db_read");
+        assert_eq!(
+            gas_category(&VmGas::Instrumentation { metered: 3 }),
+            "instrumentation"
+        );
+        assert_eq!(
+            gas_category(&VmGas::Secp256k1RecoverPubkey),
+            "secp256k1_recover_pubkey"
+        );
+    }
+
+    #[test]
+    fn only_the_admin_updates_the_contract_meta() {
+        let mut extension = extension();
+        extension.set_code(1, Some(SimpleWasmiVMCode::new(vec![0])));
+        extension.set_code(2, Some(SimpleWasmiVMCode::new(vec![0])));
+        extension.set_contract(
+            numeric_account(1),
+            Some(CosmwasmContractMeta {
+                admin: Some(numeric_account(2)),
+                ..meta(1)
+            }),
+        );
+        let migrated = CosmwasmContractMeta {
+            admin: Some(numeric_account(2)),
+            ..meta(2)
+        };
+
+        assert!(matches!(
+            extension.update_contract_meta(
+                numeric_account(3),
+                numeric_account(1),
+                migrated.clone()
+            ),
+            Err(SimpleVMError::Unauthorized)
+        ));
+        assert!(matches!(
+            extension.update_contract_meta(numeric_account(2), numeric_account(1), meta(3)),
+            Err(SimpleVMError::CodeNotFound(3))
+        ));
+        assert!(matches!(
+            extension.update_contract_meta(numeric_account(2), numeric_account(4), meta(2)),
+            Err(SimpleVMError::ContractNotFound(address)) if address == numeric_account(4)
+        ));
+
+        extension
+            .update_contract_meta(numeric_account(2), numeric_account(1), migrated.clone())
+            .unwrap();
+
+        assert_eq!(extension.contracts[&numeric_account(1)], migrated);
+    }
+
+    #[test]
+    fn contracts_without_admin_cannot_be_updated() {
+        let mut extension = extension();
+        extension.set_code(1, Some(SimpleWasmiVMCode::new(vec![0])));
+        extension.set_contract(numeric_account(1), Some(meta(1)));
+
+        assert!(matches!(
+            extension.update_contract_meta(numeric_account(1), numeric_account(1), meta(1)),
+            Err(SimpleVMError::Unauthorized)
+        ));
+    }
+
+    #[test]
+    fn sudo_is_called_without_info_and_emits_the_wasmd_event() {
+        assert_eq!(<SudoInput as AsFunctionName>::NAME, "sudo");
+        assert!(!<SudoInput as HasInfo>::HAS_INFO);
+        assert_eq!(
+            <SudoInput as HasEvent>::generate_event("contract".into()),
+            Event::new("sudo").add_attribute("_contract_address", "contract")
+        );
+    }
+
+    fn contracts(extension: &mut SimpleWasmiVMExtension, addresses: &[u64]) {
+        extension.set_code(1, Some(code(CW20_BASE)));
+        for address in addresses {
+            extension.set_contract(numeric_account(*address), Some(meta(1)));
+        }
+    }
+
+    #[test]
+    fn events_of_failed_sub_calls_are_discarded() {
+        let mut extension = extension();
+        contracts(&mut extension, &[1, 2]);
+        let mut vm = crate::bind::vm_initialize(
+            &mut extension,
+            None,
+            numeric_account(3),
+            numeric_account(1),
+            vec![],
+        )
+        .unwrap();
+        let mut events = Vec::new();
+
+        let result = vm.0.run_subvm(
+            numeric_account(2),
+            vec![],
+            &mut |event| events.push(event),
+            |_, event_handler| -> Result<(), SimpleVMError> {
+                event_handler(Event::new("failed"));
+                Err(SimpleVMError::Unsupported)
+            },
+        );
+
+        assert!(matches!(
+            result,
+            Err(SimpleVMError::InSubCall(address, error))
+                if address == numeric_account(2) && matches!(*error, SimpleVMError::Unsupported)
+        ));
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn events_of_successful_sub_calls_are_forwarded() {
+        let mut extension = extension();
+        contracts(&mut extension, &[1, 2]);
+        let mut vm = crate::bind::vm_initialize(
+            &mut extension,
+            None,
+            numeric_account(3),
+            numeric_account(1),
+            vec![],
+        )
+        .unwrap();
+        let mut events = Vec::new();
+
+        vm.0.run_subvm(
+            numeric_account(2),
+            vec![],
+            &mut |event| events.push(event),
+            |sub_vm, event_handler| -> Result<(), SimpleVMError> {
+                assert_eq!(sub_vm.0.env.contract.address, numeric_account(2).into());
+                event_handler(Event::new("succeeded"));
+                Ok(())
+            },
+        )
+        .unwrap();
+
+        assert_eq!(events, vec![Event::new("succeeded")]);
+    }
+
+    #[test]
+    fn stored_codes_get_increasing_ids_and_their_checksum() {
+        let mut extension = extension();
+
+        assert_eq!(extension.store_code(CW20_BASE.to_vec()).unwrap(), 1);
+        assert_eq!(extension.store_code(CW20_BASE.to_vec()).unwrap(), 2);
+        assert_eq!(
+            extension.codes[&1].checksum,
+            Sha256::digest(CW20_BASE).to_vec()
+        );
+        assert_eq!(extension.next_code_id, 3);
+    }
+
+    #[test]
+    fn rejected_codes_do_not_take_an_id() {
+        let mut extension = extension();
+
+        assert!(matches!(
+            extension.store_code(vec![0]),
+            Err(SimpleVMError::InvalidCode)
+        ));
+        // The smallest valid module, without any export.
+        assert!(matches!(
+            extension.store_code(b"\0asm\x01\0\0\0".to_vec()),
+            Err(SimpleVMError::MissingExport(export)) if export == "allocate"
+        ));
+        assert!(extension.codes.is_empty());
+        assert_eq!(extension.next_code_id, 1);
+    }
+
+    #[test]
+    fn rollback_reverts_stored_codes() {
+        let mut extension = extension();
+        extension.transaction_begin();
+        extension.store_code(CW20_BASE.to_vec()).unwrap();
+        extension.transaction_rollback();
+
+        assert!(extension.codes.is_empty());
+        assert_eq!(extension.next_code_id, 1);
+    }
+
+    #[test]
+    fn created_contracts_get_the_next_account() {
+        let mut extension = extension();
+        contracts(&mut extension, &[]);
+
+        assert_eq!(
+            extension
+                .create_contract(numeric_account(9), meta(1), None)
+                .unwrap(),
+            numeric_account(1)
+        );
+        assert_eq!(
+            extension
+                .create_contract(numeric_account(9), meta(1), None)
+                .unwrap(),
+            numeric_account(2)
+        );
+        assert!(matches!(
+            extension.create_contract(numeric_account(9), meta(2), None),
+            Err(SimpleVMError::CodeNotFound(2))
+        ));
+        assert_eq!(extension.contracts[&numeric_account(2)], meta(1));
+    }
+
+    #[test]
+    fn contract_addresses_are_accepted_by_the_mock_api() {
+        let mut extension = extension();
+        contracts(&mut extension, &[]);
+        let address = extension
+            .create_contract(numeric_account(9), meta(1), None)
+            .unwrap();
+
+        assert_eq!(Addr::from(address.clone()).to_string(), "001");
+
+        let mut vm =
+            crate::bind::vm_initialize(&mut extension, None, address.clone(), address, vec![])
+                .unwrap();
+        assert!(matches!(vm.0.addr_validate("001"), Ok(Ok(()))));
+        assert!(matches!(
+            vm.0.addr_validate("1"),
+            Ok(Err(SimpleVMError::InvalidAddress))
+        ));
+    }
+
+    #[test]
+    fn advancing_blocks_moves_height_and_time() {
+        let mut block = Block {
+            height: 10,
+            time: 1_000,
+            chain_id: "test".into(),
+        };
+        block.advance(3, 5);
+
+        assert_eq!(
+            block.info(),
+            BlockInfo {
+                height: 13,
+                time: Timestamp(15_000_001_000),
+                chain_id: "test".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn advancing_blocks_saturates() {
+        let mut block = Block::default();
+        block.advance(u64::MAX, u64::MAX);
+
+        assert_eq!((block.height, block.time), (u64::MAX, u64::MAX));
+    }
+
+    #[test]
+    fn moving_to_another_block_resets_the_transaction_index() {
+        let mut extension = extension();
+        extension.transaction_index = 2;
+        extension.advance_blocks(1, 5);
+
+        assert_eq!(extension.transaction_info(), TransactionInfo { index: 0 });
+
+        extension.transaction_index = 2;
+        extension.set_block_height(1);
+
+        assert_eq!(extension.transaction_info(), TransactionInfo { index: 0 });
+
+        extension.transaction_index = 2;
+        extension.set_block_time(1);
+
+        assert_eq!(extension.transaction_info(), TransactionInfo { index: 0 });
+    }
+
+    #[test]
+    fn errors_are_reported_by_kind() {
+        let failure = SimpleVMError::VMError(WasmiVMError::SystemError(
+            SystemError::ContractExecutionFailure("boom".into()),
+        ));
+
+        assert_eq!(failure.kind(), SimpleVMErrorKind::ContractExecutionFailure);
+        assert_eq!(format!("{}", failure), "contract execution failed: boom");
+        assert_eq!(
+            SimpleVMError::BalanceOverflow.kind(),
+            SimpleVMErrorKind::BalanceOverflow
+        );
+        assert_eq!(
+            SimpleVMError::ContractAlreadyExists(numeric_account(1)).kind(),
+            SimpleVMErrorKind::ContractAlreadyExists
+        );
+        assert_eq!(
+            SimpleVMError::InvalidSalt.kind(),
+            SimpleVMErrorKind::InvalidSalt
+        );
+        assert_eq!(
+            SimpleVMError::MissingExport("allocate".into()).kind(),
+            SimpleVMErrorKind::InvalidCode
+        );
+        assert_eq!(
+            format!("{}", SimpleVMError::ContractNotFound(numeric_account(2))),
+            "contract 002 not found"
+        );
+    }
+
+    #[test]
+    fn host_errors_keep_their_kind_through_the_interpreter() {
+        let host_error = |error: SimpleVMError| {
+            SimpleVMError::from(wasmi::Error::Host(alloc::boxed::Box::new(error)))
+        };
+
+        assert!(matches!(
+            host_error(SimpleVMError::OutOfGas),
+            SimpleVMError::OutOfGas
+        ));
+        assert_eq!(
+            host_error(SimpleVMError::Unauthorized).kind(),
+            SimpleVMErrorKind::Unauthorized
+        );
+        assert_eq!(
+            SimpleVMError::from(wasmi::Error::Instantiation("unknown import".into())).kind(),
+            SimpleVMErrorKind::Interpreter
+        );
+    }
+
+    #[test]
+    fn debug_messages_are_tagged_with_the_contract_and_its_depth() {
+        let mut extension = extension();
+        contracts(&mut extension, &[1, 2]);
+        let streamed = Rc::new(core::cell::RefCell::new(Vec::new()));
+        let sink = streamed.clone();
+        extension.debug_handler = DebugHandler(Some(Rc::new(move |message: &DebugMessage| {
+            sink.borrow_mut().push(message.clone())
+        })));
+        let mut vm = crate::bind::vm_initialize(
+            &mut extension,
+            None,
+            numeric_account(3),
+            numeric_account(1),
+            vec![],
+        )
+        .unwrap();
+
+        VMBase::debug(&mut vm.0, b"top".to_vec()).unwrap();
+        vm.0.run_subvm(numeric_account(2), vec![], &mut |_| {}, |sub_vm, _| {
+            VMBase::debug(&mut sub_vm.0, b"sub".to_vec())
+        })
+        .unwrap();
+        assert!(matches!(
+            vm.0.abort("boom".into()),
+            Err(e) if e.kind() == SimpleVMErrorKind::ContractExecutionFailure
+        ));
+
+        let message = |kind, address, depth, message: &str| DebugMessage {
+            kind,
+            address: numeric_account(address),
+            depth,
+            message: message.into(),
+        };
+        let expected = vec![
+            message(DebugMessageKind::Debug, 1, 0, "top"),
+            message(DebugMessageKind::Debug, 2, 1, "sub"),
+            message(DebugMessageKind::Abort, 1, 0, "boom"),
+        ];
+        assert_eq!(extension.debug_messages, expected);
+        assert_eq!(*streamed.borrow(), expected);
+    }
+
+    #[test]
+    fn contract_info_reports_the_creator_and_admin() {
+        let mut extension = extension();
+        contracts(&mut extension, &[]);
+        let address = extension
+            .create_contract(
+                numeric_account(10),
+                CosmwasmContractMeta {
+                    admin: Some(numeric_account(11)),
+                    ..meta(1)
+                },
+                None,
+            )
+            .unwrap();
+        let mut vm = crate::bind::vm_initialize(
+            &mut extension,
+            None,
+            address.clone(),
+            address.clone(),
+            vec![],
+        )
+        .unwrap();
+
+        let info = vm.0.query_info(address).unwrap();
+
+        assert_eq!(info.code_id, 1);
+        assert_eq!(info.creator, "010");
+        assert_eq!(info.admin.as_deref(), Some("011"));
+        assert!(matches!(
+            vm.0.query_info(numeric_account(5)),
+            Err(SimpleVMError::ContractNotFound(address)) if address == numeric_account(5)
+        ));
+    }
+
+    #[test]
+    fn rollback_forgets_the_creator() {
+        let mut extension = extension();
+        contracts(&mut extension, &[]);
+        extension.transaction_begin();
+        extension
+            .create_contract(numeric_account(10), meta(1), None)
+            .unwrap();
+        extension.transaction_rollback();
+
+        assert!(extension.creators.is_empty());
+    }
+
+    #[test]
+    fn numeric_accounts_are_zero_padded() {
+        let mode = AddressMode::Numeric;
+
+        assert_eq!(
+            mode.contract_address(1, 1).unwrap(),
+            BankAccount("001".into())
+        );
+        assert_eq!(
+            mode.contract_address(1, 1234).unwrap(),
+            BankAccount("1234".into())
+        );
+        assert_eq!(
+            mode.humanize(&mode.canonicalize("001").unwrap()).unwrap(),
+            BankAccount("001".into())
+        );
+        assert!(matches!(
+            mode.canonicalize("1"),
+            Err(SimpleVMError::InvalidAddress)
+        ));
+        assert!(matches!(
+            mode.humanize(&mode.canonicalize("abc").unwrap()),
+            Err(SimpleVMError::InvalidAddress)
+        ));
+    }
+
+    #[test]
+    fn bech32_accounts_round_trip_through_their_payload() {
+        let mode = AddressMode::bech32("juno".into()).unwrap();
+        let account = mode.contract_address(1, 1).unwrap();
+        let canonical = mode.canonicalize(&account.0).unwrap();
+
+        assert!(account.0.starts_with("juno1"));
+        assert_eq!(canonical.len(), 32);
+        assert_eq!(mode.humanize(&canonical).unwrap(), account);
+        assert_eq!(mode.humanize(&[0x42; 20]).unwrap().0.len(), 43);
+    }
+
+    #[test]
+    fn bech32_rejects_foreign_or_malformed_addresses() {
+        let mode = AddressMode::bech32("juno".into()).unwrap();
+        let osmo = AddressMode::bech32("osmo".into()).unwrap();
+        let account = osmo.contract_address(1, 1).unwrap();
+
+        assert!(matches!(
+            mode.canonicalize(&account.0),
+            Err(SimpleVMError::InvalidAddress)
+        ));
+        assert!(matches!(
+            mode.canonicalize("juno1invalid"),
+            Err(SimpleVMError::InvalidAddress)
+        ));
+        assert!(matches!(
+            mode.humanize(&[0; 8]),
+            Err(SimpleVMError::InvalidAddress)
+        ));
+        assert!(matches!(
+            AddressMode::bech32("Juno".into()),
+            Err(SimpleVMError::InvalidAddress)
+        ));
+    }
+
+    #[test]
+    fn contract_addresses_follow_wasmd_classic_derivation() {
+        let mode = AddressMode::bech32("juno".into()).unwrap();
+
+        assert_eq!(
+            mode.contract_address(1, 1).unwrap(),
+            BankAccount("juno14hj2tavq8fpesdwxxcu44rty3hh90vhujrvcmstl4zr3txmfvw9skjuwg8".into())
+        );
+    }
+
+    #[test]
+    fn predictable_addresses_follow_wasmd_instantiate2_derivation() {
+        let mode = AddressMode::bech32("purple".into()).unwrap();
+        let checksum =
+            hex::decode("13a1fc994cc6d1c81b746ee0c0ff6f90043875e0bf1d9be6b7d779fc978dc2a5")
+                .unwrap();
+        let creator = BankAccount("purple1nxvenxve42424242hwamhwamenxvenxvhxf2py".into());
+
+        assert_eq!(
+            mode.predictable_contract_address(&checksum, &creator, &[0x61])
+                .unwrap(),
+            BankAccount("purple1t6r960j945lfv8mhl4mage2rg97w63xeynwrupum2s2l7em4lprs9ce5hk".into())
+        );
+        assert!(matches!(
+            mode.predictable_contract_address(&checksum, &creator, &[]),
+            Err(SimpleVMError::InvalidSalt)
+        ));
+        assert!(matches!(
+            mode.predictable_contract_address(&checksum, &creator, &[0; 65]),
+            Err(SimpleVMError::InvalidSalt)
+        ));
+        assert!(matches!(
+            AddressMode::Numeric.predictable_contract_address(&checksum, &creator, &[0x61]),
+            Err(SimpleVMError::Unsupported)
+        ));
+    }
+
+    #[test]
+    fn predictable_addresses_cannot_be_taken_twice() {
+        let mut extension = SimpleWasmiVMExtension::new(
+            DEFAULT_GAS_LIMIT,
+            AddressMode::bech32("purple".into()).unwrap(),
+        );
+        contracts(&mut extension, &[]);
+        let creator = BankAccount("purple1nxvenxve42424242hwamhwamenxvenxvhxf2py".into());
+        let address = extension
+            .create_contract(creator.clone(), meta(1), Some(b"salt".as_slice()))
+            .unwrap();
+
+        assert!(matches!(
+            extension.create_contract(creator, meta(1), Some(b"salt".as_slice())),
+            Err(SimpleVMError::ContractAlreadyExists(existing)) if existing == address
+        ));
+    }
+
+    #[test]
+    fn taken_classic_addresses_do_not_consume_an_instance_id() {
+        let mut extension = extension();
+        contracts(&mut extension, &[]);
+        let taken = extension.address_mode.contract_address(1, 1).unwrap();
+        extension.set_contract(taken.clone(), Some(meta(1)));
+
+        assert!(matches!(
+            extension.create_contract(numeric_account(9), meta(1), None),
+            Err(SimpleVMError::ContractAlreadyExists(address)) if address == taken
+        ));
+        assert_eq!(extension.next_account_id, 1);
+    }
+
+    #[test]
+    fn named_accounts_are_created_once() {
+        let mut extension = extension();
+        let alice = extension
+            .named_account("alice", &[coin(100, "uatom")])
+            .unwrap();
+
+        assert_eq!(extension.named_account("alice", &[]).unwrap(), alice);
+        assert_ne!(extension.named_account("bob", &[]).unwrap(), alice);
+        assert_eq!(extension.accounts["alice"], alice);
+        assert_eq!(extension.balance(&alice, "uatom"), 100);
+    }
+
+    #[test]
+    fn named_accounts_are_valid_accounts_of_the_address_mode() {
+        let numeric = AddressMode::Numeric;
+        let bech32 = AddressMode::bech32("juno".into()).unwrap();
+        let alice = numeric.named_account("alice").unwrap();
+
+        assert!(numeric.canonicalize(&alice.0).is_ok());
+        assert_eq!(
+            bech32
+                .canonicalize(&bech32.named_account("alice").unwrap().0)
+                .unwrap(),
+            Sha256::digest(b"alice")[..20].to_vec()
+        );
+    }
+
+    #[test]
+    fn admin_changes_are_forwarded_at_the_next_sub_call() {
+        let mut extension = extension();
+        contracts(&mut extension, &[1, 2]);
+        extension.set_contract(
+            numeric_account(2),
+            Some(CosmwasmContractMeta {
+                admin: Some(numeric_account(1)),
+                ..meta(1)
+            }),
+        );
+        let mut vm = crate::bind::vm_initialize(
+            &mut extension,
+            None,
+            numeric_account(3),
+            numeric_account(1),
+            vec![],
+        )
+        .unwrap();
+        let mut events = Vec::new();
+
+        VMBase::set_contract_meta(&mut vm.0, numeric_account(2), meta(1)).unwrap();
+        VMBase::set_contract_meta(&mut vm.0, numeric_account(2), meta(1)).unwrap_err();
+        vm.0.run_subvm(
+            numeric_account(2),
+            vec![],
+            &mut |event| events.push(event),
+            |_, event_handler| -> Result<(), SimpleVMError> {
+                event_handler(Event::new("succeeded"));
+                Ok(())
+            },
+        )
+        .unwrap();
+
+        assert!(vm.0.admin_events.is_empty());
+        assert_eq!(
+            events,
+            vec![
+                update_admin_event(&numeric_account(2), None),
+                Event::new("succeeded")
+            ]
+        );
+    }
+
+    #[test]
+    fn admin_changes_of_sub_calls_are_forwarded_once_they_succeeded() {
+        let mut extension = extension();
+        contracts(&mut extension, &[1]);
+        extension.set_contract(
+            numeric_account(2),
+            Some(CosmwasmContractMeta {
+                admin: Some(numeric_account(2)),
+                ..meta(1)
+            }),
+        );
+        let mut vm = crate::bind::vm_initialize(
+            &mut extension,
+            None,
+            numeric_account(3),
+            numeric_account(1),
+            vec![],
+        )
+        .unwrap();
+        fn change_admin(sub_vm: &mut WasmiVM<SimpleWasmiVM>) -> Result<(), SimpleVMError> {
+            let admin = CosmwasmContractMeta {
+                admin: Some(numeric_account(4)),
+                ..meta(1)
+            };
+            VMBase::set_contract_meta(&mut sub_vm.0, numeric_account(2), admin)
+        }
+        let mut events = Vec::new();
+
+        let result = vm.0.run_subvm(
+            numeric_account(2),
+            vec![],
+            &mut |event| events.push(event),
+            |sub_vm, _| -> Result<(), SimpleVMError> {
+                change_admin(sub_vm)?;
+                Err(SimpleVMError::Unsupported)
+            },
+        );
+        assert_eq!(result.unwrap_err().kind(), SimpleVMErrorKind::Unsupported);
+        assert!(events.is_empty());
+
+        // Reverted by the system along with the rest of the failed sub message.
+        vm.0.extension.set_contract(
+            numeric_account(2),
+            Some(CosmwasmContractMeta {
+                admin: Some(numeric_account(2)),
+                ..meta(1)
+            }),
+        );
+        vm.0.run_subvm(
+            numeric_account(2),
+            vec![],
+            &mut |event| events.push(event),
+            |sub_vm, _| change_admin(sub_vm),
+        )
+        .unwrap();
+        assert_eq!(
+            events,
+            vec![update_admin_event(
+                &numeric_account(2),
+                Some(&numeric_account(4))
+            )]
+        );
+    }
+
+    #[test]
+    fn sub_call_errors_carry_the_contract_they_failed_in() {
+        let mut extension = extension();
+        contracts(&mut extension, &[1, 2, 3]);
+        let mut vm = crate::bind::vm_initialize(
+            &mut extension,
+            None,
+            numeric_account(4),
+            numeric_account(1),
+            vec![],
+        )
+        .unwrap();
+
+        let error =
+            vm.0.run_subvm(
+                numeric_account(2),
+                vec![],
+                &mut |_| {},
+                |sub_vm, event_handler| -> Result<(), SimpleVMError> {
+                    sub_vm
+                        .0
+                        .run_subvm(numeric_account(3), vec![], event_handler, |_, _| {
+                            Err(SimpleVMError::Unauthorized)
+                        })
+                },
+            )
+            .unwrap_err();
+
+        assert_eq!(error.sub_call_address(), Some(&numeric_account(3)));
+        assert_eq!(error.kind(), SimpleVMErrorKind::Unauthorized);
+        assert_eq!(format!("{}", error), "unauthorized");
+    }
+
+    #[test]
+    fn malformed_crypto_inputs_fail_the_call() {
+        let mut extension = extension();
+        contracts(&mut extension, &[1]);
+        let mut vm = crate::bind::vm_initialize(
+            &mut extension,
+            None,
+            numeric_account(2),
+            numeric_account(1),
+            vec![],
+        )
+        .unwrap();
+
+        let error =
+            VMBase::secp256k1_verify(&mut vm.0, &[0; 31], &[0; 64], &[0x02; 33]).unwrap_err();
+        assert!(matches!(
+            error,
+            SimpleVMError::Crypto(crypto::CryptoError::InvalidHashFormat)
+        ));
+        assert_eq!(error.kind(), SimpleVMErrorKind::Crypto);
+        assert!(matches!(
+            VMBase::secp256k1_recover_pubkey(&mut vm.0, &[0; 32], &[1; 64], 2),
+            Err(SimpleVMError::Crypto(
+                crypto::CryptoError::InvalidRecoveryParam
+            ))
+        ));
+    }
+}